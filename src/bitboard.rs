@@ -0,0 +1,246 @@
+use crate::board::Position;
+pub use crate::magic::{bishop_attacks, rook_attacks};
+use crate::pieces::Color;
+
+/// One bit per square, bit `row * 8 + col`, so a1 is bit 0 and h8 bit 63
+pub type Bitboard = u64;
+
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [
+    (2, 1),
+    (2, -1),
+    (-2, 1),
+    (-2, -1),
+    (1, 2),
+    (1, -2),
+    (-1, 2),
+    (-1, -2),
+];
+
+const KING_OFFSETS: [(i8, i8); 8] = [
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, -1),
+    (0, 1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+pub const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+pub const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// Squares a piece on each square reaches with one of the offsets
+const fn leaper_table(offsets: &[(i8, i8)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let row = (square / 8) as i8;
+        let col = (square % 8) as i8;
+        let mut i = 0;
+        while i < offsets.len() {
+            let to_row = row + offsets[i].0;
+            let to_col = col + offsets[i].1;
+            if to_row >= 0 && to_row < 8 && to_col >= 0 && to_col < 8 {
+                table[square] |= 1 << (to_row * 8 + to_col);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+pub const KNIGHT_ATTACKS: [Bitboard; 64] = leaper_table(&KNIGHT_OFFSETS);
+pub const KING_ATTACKS: [Bitboard; 64] = leaper_table(&KING_OFFSETS);
+/// the squares a pawn of each colour attacks, white first
+pub const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    leaper_table(&[(1, -1), (1, 1)]),
+    leaper_table(&[(-1, -1), (-1, 1)]),
+];
+
+/// For two squares on the same rank, file or diagonal: the squares strictly
+/// between them (`between`) and the whole line through both (`line`)
+const fn line_tables() -> ([[Bitboard; 64]; 64], [[Bitboard; 64]; 64]) {
+    let mut between = [[0; 64]; 64];
+    let mut line = [[0; 64]; 64];
+    let directions = [
+        (1, 0),
+        (-1, 0),
+        (0, 1),
+        (0, -1),
+        (1, 1),
+        (1, -1),
+        (-1, 1),
+        (-1, -1),
+    ];
+
+    let mut from = 0;
+    while from < 64 {
+        let mut d = 0;
+        while d < directions.len() {
+            let (row_offset, col_offset) = directions[d];
+
+            // the full line through `from` in this direction, both ways
+            let mut full: Bitboard = 1 << from;
+            let mut sign = -1;
+            while sign <= 1 {
+                let mut row = (from / 8) as i8 + sign * row_offset;
+                let mut col = (from % 8) as i8 + sign * col_offset;
+                while row >= 0 && row < 8 && col >= 0 && col < 8 {
+                    full |= 1 << (row * 8 + col);
+                    row += sign * row_offset;
+                    col += sign * col_offset;
+                }
+                sign += 2;
+            }
+
+            let mut path: Bitboard = 0;
+            let mut row = (from / 8) as i8 + row_offset;
+            let mut col = (from % 8) as i8 + col_offset;
+            while row >= 0 && row < 8 && col >= 0 && col < 8 {
+                let to = (row * 8 + col) as usize;
+                between[from][to] = path;
+                line[from][to] = full;
+                path |= 1 << to;
+                row += row_offset;
+                col += col_offset;
+            }
+            d += 1;
+        }
+        from += 1;
+    }
+    (between, line)
+}
+
+static LINES: ([[Bitboard; 64]; 64], [[Bitboard; 64]; 64]) = line_tables();
+
+/// Squares strictly between a and b, empty if they are not on a line
+pub fn between(a: usize, b: usize) -> Bitboard {
+    LINES.0[a][b]
+}
+
+/// The whole rank, file or diagonal through a and b, empty if there is none
+pub fn line(a: usize, b: usize) -> Bitboard {
+    LINES.1[a][b]
+}
+
+pub fn pawn_attacks(color: Color, square: usize) -> Bitboard {
+    PAWN_ATTACKS[color.index()][square]
+}
+
+/// Walks each direction until the edge or the first piece, the piece itself
+/// is included. Slow, only used to fill the magic tables and check them.
+pub fn sliding_attacks(square: usize, occupied: Bitboard, directions: &[(i8, i8)]) -> Bitboard {
+    let mut attacks = 0;
+    for &(row_offset, col_offset) in directions {
+        let mut row = (square / 8) as i8 + row_offset;
+        let mut col = (square % 8) as i8 + col_offset;
+        while (0..8).contains(&row) && (0..8).contains(&col) {
+            let bit = 1 << (row * 8 + col);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
+            }
+            row += row_offset;
+            col += col_offset;
+        }
+    }
+    attacks
+}
+
+pub fn queen_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+/// Iterates over the squares of a bitboard, lowest bit first
+#[derive(Debug, Clone, Copy)]
+pub struct Squares(pub Bitboard);
+
+impl Iterator for Squares {
+    type Item = Position;
+
+    fn next(&mut self) -> Option<Position> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(Position::from_index(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(squares: &[Position]) -> Bitboard {
+        squares.iter().fold(0, |bb, pos| bb | pos.bit())
+    }
+
+    #[test]
+    fn test_leaper_tables() {
+        assert_eq!(KNIGHT_ATTACKS[Position::A1.index()].count_ones(), 2);
+        assert_eq!(KNIGHT_ATTACKS[Position::D4.index()].count_ones(), 8);
+        assert_eq!(KING_ATTACKS[Position::H8.index()].count_ones(), 3);
+        assert_eq!(
+            pawn_attacks(Color::White, Position::A2.index()),
+            bits(&[Position::B3])
+        );
+        assert_eq!(
+            pawn_attacks(Color::Black, Position::E7.index()),
+            bits(&[Position::D6, Position::F6])
+        );
+    }
+
+    #[test]
+    fn test_sliding_attacks_stop_at_blockers() {
+        let occupied = bits(&[Position::D6, Position::F4, Position::B2]);
+        let rook = rook_attacks(Position::D4.index(), occupied);
+        assert_eq!(
+            rook,
+            bits(&[
+                Position::D5,
+                Position::D6,
+                Position::D3,
+                Position::D2,
+                Position::D1,
+                Position::E4,
+                Position::F4,
+                Position::C4,
+                Position::B4,
+                Position::A4,
+            ])
+        );
+
+        let bishop = bishop_attacks(Position::D4.index(), occupied);
+        assert!(bishop & Position::B2.bit() != 0);
+        assert!(bishop & Position::A1.bit() == 0);
+        assert_eq!(bishop.count_ones(), 12);
+    }
+
+    #[test]
+    fn test_between_and_line() {
+        let (a1, c3, h8) = (
+            Position::A1.index(),
+            Position::C3.index(),
+            Position::H8.index(),
+        );
+        assert_eq!(between(a1, c3), Position::B2.bit());
+        assert_eq!(between(a1, h8).count_ones(), 6);
+        assert_eq!(line(c3, a1), line(a1, h8));
+        assert_eq!(between(a1, Position::B3.index()), 0);
+        assert_eq!(line(a1, Position::B3.index()), 0);
+        assert_eq!(
+            line(Position::E1.index(), Position::E4.index()).count_ones(),
+            8
+        );
+    }
+
+    #[test]
+    fn test_squares_iterator() {
+        let squares: Vec<Position> =
+            Squares(bits(&[Position::H8, Position::A1, Position::E4])).collect();
+        assert_eq!(squares, vec![Position::A1, Position::E4, Position::H8]);
+    }
+}
@@ -1,4 +1,11 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use crate::bitboard::{Bitboard, Squares, pawn_attacks};
 use crate::pieces::{Color, Piece, PieceType};
+use crate::rules::Undo;
+use crate::zobrist;
 
 pub const BOARD_ROWS: i8 = 8;
 pub const BOARD_COLS: i8 = 8;
@@ -6,39 +13,227 @@ pub const BOARD_COLS: i8 = 8;
 // ASCII board
 pub const FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Position {
     pub row: i8,
     pub col: i8,
 }
 
+/// Declares the named squares of one rank, `A1 = 0` becomes `Position::A1`
+macro_rules! square_constants {
+    ($row:expr; $($name:ident = $col:expr),*) => {
+        $(pub const $name: Position = Position { row: $row, col: $col };)*
+    };
+}
+
 impl Position {
-    pub fn new(row: i8, col: i8) -> Self {
+    square_constants!(0; A1 = 0, B1 = 1, C1 = 2, D1 = 3, E1 = 4, F1 = 5, G1 = 6, H1 = 7);
+    square_constants!(1; A2 = 0, B2 = 1, C2 = 2, D2 = 3, E2 = 4, F2 = 5, G2 = 6, H2 = 7);
+    square_constants!(2; A3 = 0, B3 = 1, C3 = 2, D3 = 3, E3 = 4, F3 = 5, G3 = 6, H3 = 7);
+    square_constants!(3; A4 = 0, B4 = 1, C4 = 2, D4 = 3, E4 = 4, F4 = 5, G4 = 6, H4 = 7);
+    square_constants!(4; A5 = 0, B5 = 1, C5 = 2, D5 = 3, E5 = 4, F5 = 5, G5 = 6, H5 = 7);
+    square_constants!(5; A6 = 0, B6 = 1, C6 = 2, D6 = 3, E6 = 4, F6 = 5, G6 = 6, H6 = 7);
+    square_constants!(6; A7 = 0, B7 = 1, C7 = 2, D7 = 3, E7 = 4, F7 = 5, G7 = 6, H7 = 7);
+    square_constants!(7; A8 = 0, B8 = 1, C8 = 2, D8 = 3, E8 = 4, F8 = 5, G8 = 6, H8 = 7);
+
+    pub const fn new(row: i8, col: i8) -> Self {
         Self { row, col }
     }
 
+    /// The bit of this square in a bitboard, a1 is 0 and h8 is 63
+    pub fn index(&self) -> usize {
+        (self.row * BOARD_COLS + self.col) as usize
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        Self {
+            row: (index / BOARD_COLS as usize) as i8,
+            col: (index % BOARD_COLS as usize) as i8,
+        }
+    }
+
+    pub fn bit(&self) -> Bitboard {
+        1 << self.index()
+    }
+
     pub fn delta(&self, other: Position) -> (i8, i8) {
         (other.row - self.row, other.col - self.col)
     }
 }
 
-#[derive(Clone)]
+/// The square name was not a file a-h followed by a rank 1-8
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSquareError(pub String);
+
+impl fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid square '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseSquareError {}
+
+impl FromStr for Position {
+    type Err = ParseSquareError;
+
+    /// parses an algebraic square like "e4", row 3 col 4
+    fn from_str(square: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseSquareError(square.to_string());
+        let mut chars = square.chars();
+        let file = chars.next().ok_or_else(invalid)?;
+        let rank = chars.next().ok_or_else(invalid)?;
+        if chars.next().is_some() || !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+            return Err(invalid());
+        }
+        Ok(Position {
+            row: rank as i8 - b'1' as i8,
+            col: file as i8 - b'a' as i8,
+        })
+    }
+}
+
+impl fmt::Display for Position {
+    /// writes the algebraic square name, positions off the board are written as (row, col)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if (0..BOARD_ROWS).contains(&self.row) && (0..BOARD_COLS).contains(&self.col) {
+            let file = (b'a' + self.col as u8) as char;
+            let rank = (b'1' + self.row as u8) as char;
+            write!(f, "{file}{rank}")
+        } else {
+            write!(f, "({}, {})", self.row, self.col)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CastleSide {
+    KingSide,
+    QueenSide,
+}
+
+/// Castling availability, the third field of a FEN string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+}
+
+impl CastlingRights {
+    pub fn all() -> Self {
+        Self {
+            white_king_side: true,
+            white_queen_side: true,
+            black_king_side: true,
+            black_queen_side: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn has(&self, color: Color, side: CastleSide) -> bool {
+        match (color, side) {
+            (Color::White, CastleSide::KingSide) => self.white_king_side,
+            (Color::White, CastleSide::QueenSide) => self.white_queen_side,
+            (Color::Black, CastleSide::KingSide) => self.black_king_side,
+            (Color::Black, CastleSide::QueenSide) => self.black_queen_side,
+        }
+    }
+
+    pub fn has_any(&self, color: Color) -> bool {
+        self.has(color, CastleSide::KingSide) || self.has(color, CastleSide::QueenSide)
+    }
+
+    pub fn set(&mut self, color: Color, side: CastleSide, value: bool) {
+        match (color, side) {
+            (Color::White, CastleSide::KingSide) => self.white_king_side = value,
+            (Color::White, CastleSide::QueenSide) => self.white_queen_side = value,
+            (Color::Black, CastleSide::KingSide) => self.black_king_side = value,
+            (Color::Black, CastleSide::QueenSide) => self.black_queen_side = value,
+        }
+    }
+
+    /// removes the rights of a color, used when the king moves
+    pub fn remove_color(&mut self, color: Color) {
+        self.set(color, CastleSide::KingSide, false);
+        self.set(color, CastleSide::QueenSide, false);
+    }
+
+    /// removes the right tied to a rook corner, used when a rook moves away from it
+    /// or gets captured on it
+    pub fn remove_corner(&mut self, pos: Position) {
+        match (pos.row, pos.col) {
+            (0, 0) => self.white_queen_side = false,
+            (0, 7) => self.white_king_side = false,
+            (7, 0) => self.black_queen_side = false,
+            (7, 7) => self.black_king_side = false,
+            _ => {}
+        }
+    }
+}
+
+/// Two boards are equal when the position is, like for the repetition rules:
+/// same pieces, side to move, castling rights and en passant file. The move
+/// history, the clocks and has_moved are left out, and Hash uses the zobrist key.
+#[derive(Debug, Clone)]
 pub struct Board {
-    pub squares: [[Option<Piece>; BOARD_COLS as usize]; BOARD_ROWS as usize],
+    /// one bitboard per colour and piece type, `bitboards[color][piece_type]`
+    pub bitboards: [[Bitboard; 6]; 2],
+    /// every piece of a colour
+    pub occupancy: [Bitboard; 2],
+    /// pieces with has_moved set
+    pub moved: Bitboard,
     pub move_turn: Color,
     pub white_king: Option<Position>,
     pub black_king: Option<Position>, //cache the kings insted of looping through board looking for it??? good??
     pub en_passant: Option<Position>,
+    pub castling: CastlingRights,
+    /// half moves since the last capture or pawn move
+    pub halfmove_clock: u32,
+    /// starts at 1 and goes up after every black move
+    pub fullmove_number: u32,
+    /// one entry per move played, popped by unmake_move
+    pub history: Vec<Undo>,
+    /// Zobrist key of the position, kept up to date by set and set_values.
+    /// Covers the same things as position_key.
+    pub zobrist: u64,
+}
+
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.bitboards == other.bitboards
+            && self.move_turn == other.move_turn
+            && self.castling == other.castling
+            && self.en_passant_zobrist() == other.en_passant_zobrist()
+    }
+}
+
+impl Eq for Board {}
+
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.zobrist.hash(state);
+    }
 }
 
 impl Default for Board {
     fn default() -> Self {
         Board {
-            squares: [[None; BOARD_COLS as usize]; BOARD_ROWS as usize],
+            bitboards: [[0; 6]; 2],
+            occupancy: [0; 2],
+            moved: 0,
             move_turn: Color::White,
             white_king: None,
             black_king: None,
             en_passant: None,
+            castling: CastlingRights::none(),
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: Vec::new(),
+            zobrist: 0,
         }
     }
 }
@@ -49,11 +244,57 @@ impl Board {
         pos.row >= 0 && pos.row < BOARD_ROWS && pos.col >= 0 && pos.col < BOARD_COLS
     }
 
+    /// Copies the position but not the move history, cheaper than clone when
+    /// we only want to try a move out
+    pub fn clone_position(&self) -> Board {
+        Board {
+            bitboards: self.bitboards,
+            occupancy: self.occupancy,
+            moved: self.moved,
+            move_turn: self.move_turn,
+            white_king: self.white_king,
+            black_king: self.black_king,
+            en_passant: self.en_passant,
+            castling: self.castling,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            history: Vec::new(),
+            zobrist: self.zobrist,
+        }
+    }
+
     pub fn get(&self, position: Position) -> Option<Piece> {
         if !self.pos_on_board(position) {
             return None;
         }
-        self.squares[position.row as usize][position.col as usize]
+        let bit = position.bit();
+        let color = if self.occupancy[Color::White.index()] & bit != 0 {
+            Color::White
+        } else if self.occupancy[Color::Black.index()] & bit != 0 {
+            Color::Black
+        } else {
+            return None;
+        };
+        let piece_type = PieceType::ALL
+            .into_iter()
+            .find(|pt| self.bitboards[color.index()][pt.index()] & bit != 0)
+            .expect("validated: occupancy matches bitboards");
+
+        Some(Piece {
+            piece_type,
+            color,
+            has_moved: self.moved & bit != 0,
+        })
+    }
+
+    /// The bitboard of one kind of piece
+    pub fn pieces(&self, color: Color, piece_type: PieceType) -> Bitboard {
+        self.bitboards[color.index()][piece_type.index()]
+    }
+
+    /// Every piece of both colours
+    pub fn occupied(&self) -> Bitboard {
+        self.occupancy[0] | self.occupancy[1]
     }
 
     pub fn set(&mut self, position: Position, piece: Option<Piece>) {
@@ -70,53 +311,108 @@ impl Board {
             }
         }
 
-        self.squares[position.row as usize][position.col as usize] = piece;
+        let bit = position.bit();
+        if let Some(old) = self.get(position) {
+            self.bitboards[old.color.index()][old.piece_type.index()] &= !bit;
+            self.occupancy[old.color.index()] &= !bit;
+            self.zobrist ^= zobrist::piece(old.color, old.piece_type, position.index());
+        }
+        self.moved &= !bit;
+
+        if let Some(p) = piece {
+            self.bitboards[p.color.index()][p.piece_type.index()] |= bit;
+            self.occupancy[p.color.index()] |= bit;
+            self.zobrist ^= zobrist::piece(p.color, p.piece_type, position.index());
+            if p.has_moved {
+                self.moved |= bit;
+            }
+        }
     }
 
-    pub fn setup_fen(&mut self, fen: &str) {
-        self.squares = [[None; BOARD_COLS as usize]; BOARD_ROWS as usize];
-        self.white_king = None;
-        self.black_king = None;
-        self.en_passant = None;
+    /// The parts of the position that decide if two positions are the same for
+    /// the repetition rules
+    pub fn position_key(&self) -> PositionKey {
+        PositionKey {
+            bitboards: self.bitboards,
+            move_turn: self.move_turn,
+            castling: self.castling,
+            en_passant: self.en_passant.filter(|&ep| self.en_passant_capturable(ep)),
+        }
+    }
 
-        let mut fen_row = 0;
-        let mut col = 0;
-        let mut parts = fen.split_whitespace();
-        let board_part = parts.next().unwrap_or("");
-        for ch in board_part.chars() {
-            match ch {
-                '/' => {
-                    fen_row += 1;
-                    col = 0;
-                }
-                '1'..='8' => {
-                    col += ch.to_digit(10).unwrap() as i8;
-                }
-                _ => {
-                    // Flip the row so that FEN's row 0 becomes board's row 7
-                    let row = BOARD_ROWS - 1 - fen_row;
-                    let pos = Position { row, col };
-                    let piece = match ch {
-                        'p' => Some(Piece::new(PieceType::Pawn, Color::Black)),
-                        'r' => Some(Piece::new(PieceType::Rook, Color::Black)),
-                        'n' => Some(Piece::new(PieceType::Knight, Color::Black)),
-                        'b' => Some(Piece::new(PieceType::Bishop, Color::Black)),
-                        'q' => Some(Piece::new(PieceType::Queen, Color::Black)),
-                        'k' => Some(Piece::new(PieceType::King, Color::Black)),
-                        'P' => Some(Piece::new(PieceType::Pawn, Color::White)),
-                        'R' => Some(Piece::new(PieceType::Rook, Color::White)),
-                        'N' => Some(Piece::new(PieceType::Knight, Color::White)),
-                        'B' => Some(Piece::new(PieceType::Bishop, Color::White)),
-                        'Q' => Some(Piece::new(PieceType::Queen, Color::White)),
-                        'K' => Some(Piece::new(PieceType::King, Color::White)),
-                        _ => None,
-                    };
-                    self.set(pos, piece);
-                    col += 1;
+    /// The Zobrist key worked out from scratch, equal to the `zobrist` field
+    /// unless fields were changed by hand. Call this after doing that.
+    pub fn compute_zobrist(&self) -> u64 {
+        let mut key = self.en_passant_zobrist() ^ zobrist::castling(self.castling);
+        if self.move_turn == Color::Black {
+            key ^= zobrist::black_to_move();
+        }
+        for color in [Color::White, Color::Black] {
+            for piece_type in PieceType::ALL {
+                for pos in Squares(self.pieces(color, piece_type)) {
+                    key ^= zobrist::piece(color, piece_type, pos.index());
                 }
             }
         }
+        key
+    }
+
+    /// The en passant part of the key, only there if a pawn of the side to move
+    /// can legally take, same as in position_key
+    pub fn en_passant_zobrist(&self) -> u64 {
+        match self.en_passant {
+            // the pawn_attacks check is only there to skip check_move most of the time
+            Some(ep)
+                if pawn_attacks(self.move_turn.opposite(), ep.index())
+                    & self.pieces(self.move_turn, PieceType::Pawn)
+                    != 0
+                    && self.en_passant_capturable(ep) =>
+            {
+                zobrist::en_passant(ep.col)
+            }
+            _ => 0,
+        }
     }
+
+    /// An en passant square only counts if a pawn can legally take on it
+    fn en_passant_capturable(&self, ep: Position) -> bool {
+        let pawn_row = match self.move_turn {
+            Color::White => ep.row - 1,
+            Color::Black => ep.row + 1,
+        };
+        let info = self.check_info();
+        [ep.col - 1, ep.col + 1].into_iter().any(|col| {
+            let from = Position::new(pawn_row, col);
+            self.get(from)
+                .is_some_and(|p| p.piece_type == PieceType::Pawn && p.color == self.move_turn)
+                && self.check_move_with(&info, from, ep).is_ok()
+        })
+    }
+
+    /// How many times the current position has been on the board, counting now.
+    /// Only looks back to the last capture or pawn move, nothing before that can
+    /// repeat. Compares the zobrist keys kept in the history.
+    pub fn repetition_count(&self) -> usize {
+        let plies = (self.halfmove_clock as usize).min(self.history.len());
+        let earlier = self
+            .history
+            .iter()
+            .rev()
+            .take(plies)
+            .filter(|undo| undo.zobrist == self.zobrist)
+            .count();
+        1 + earlier
+    }
+}
+
+/// Placement without has_moved, side to move, castling rights and the en
+/// passant square if it can actually be taken (FIDE rules)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PositionKey {
+    pub bitboards: [[Bitboard; 6]; 2],
+    pub move_turn: Color,
+    pub castling: CastlingRights,
+    pub en_passant: Option<Position>,
 }
 
 #[cfg(test)]
@@ -126,10 +422,45 @@ mod tests {
     #[test]
     fn test_generate_starting_board() {
         let mut board = Board::default();
-        board.setup_fen(FEN);
+        board.setup_fen(FEN).unwrap();
         assert_eq!(
             board.get(Position { row: 0, col: 0 }).unwrap().piece_type,
             PieceType::Rook
         )
     }
+
+    #[test]
+    fn test_set_keeps_bitboards_in_sync() {
+        let mut board = Board::start_pos();
+        assert_eq!(board.occupied().count_ones(), 32);
+        assert_eq!(board.pieces(Color::White, PieceType::Pawn), 0xFF00);
+
+        let mut knight = board.get(Position::G1).unwrap();
+        knight.has_moved = true;
+        board.set(Position::G1, None);
+        board.set(Position::E2, Some(knight));
+
+        assert_eq!(board.get(Position::E2), Some(knight));
+        assert_eq!(board.get(Position::G1), None);
+        assert_eq!(board.pieces(Color::White, PieceType::Pawn), 0xEF00);
+        assert_eq!(board.moved, Position::E2.bit());
+        assert_eq!(board.occupancy[Color::White.index()].count_ones(), 15);
+    }
+
+    #[test]
+    fn test_position_square_names() {
+        assert_eq!("e4".parse::<Position>(), Ok(Position { row: 3, col: 4 }));
+        assert_eq!(Position::from_str("a1"), Ok(Position::A1));
+        assert_eq!(Position::from_str("h8"), Ok(Position::H8));
+        assert_eq!(Position::E4, Position::new(3, 4));
+        assert_eq!(Position::C7.to_string(), "c7");
+        assert_eq!(Position::new(8, 0).to_string(), "(8, 0)");
+
+        for bad in ["", "e", "e9", "i1", "e44", "E4"] {
+            assert_eq!(
+                bad.parse::<Position>(),
+                Err(ParseSquareError(bad.to_string()))
+            );
+        }
+    }
 }
@@ -0,0 +1,432 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::pieces::Color;
+
+/// Where the clock gets the time from, only differences between two calls matter
+pub trait TimeSource {
+    fn now(&self) -> Duration;
+}
+
+/// The real time, measured from when it was created
+#[derive(Debug, Clone, Copy)]
+pub struct RealTime {
+    start: Instant,
+}
+
+impl Default for RealTime {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl TimeSource for RealTime {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A time that only moves when told to, for tests. Clones share the same time,
+/// so keep one and give a clone to the Clock.
+#[derive(Debug, Clone, Default)]
+pub struct ManualTime(Rc<Cell<Duration>>);
+
+impl ManualTime {
+    pub fn advance(&self, by: Duration) {
+        self.0.set(self.0.get() + by);
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Duration {
+        self.0.get()
+    }
+}
+
+/// What a player gets back for a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBonus {
+    None,
+    /// Fischer, added after every move
+    Increment(Duration),
+    /// the time used is given back after the move, up to this much
+    Bronstein(Duration),
+    /// the clock only starts counting down after this much of the move has passed
+    SimpleDelay(Duration),
+}
+
+/// One part of a time control, like "40 moves in 90 minutes"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControlStage {
+    /// moves to make in this stage, None for the rest of the game
+    pub moves: Option<u32>,
+    /// added to the clock when the stage starts
+    pub time: Duration,
+    pub bonus: TimeBonus,
+}
+
+/// The stages are played in order. If the last one has a move count it is
+/// repeated, like "40 moves in 2 hours" for the whole game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    pub stages: Vec<TimeControlStage>,
+}
+
+impl TimeControl {
+    /// `base` for the whole game with `increment` after every move, like 3+2
+    pub fn fischer(base: Duration, increment: Duration) -> Self {
+        TimeControl::single(base, TimeBonus::Increment(increment))
+    }
+
+    pub fn bronstein(base: Duration, delay: Duration) -> Self {
+        TimeControl::single(base, TimeBonus::Bronstein(delay))
+    }
+
+    pub fn simple_delay(base: Duration, delay: Duration) -> Self {
+        TimeControl::single(base, TimeBonus::SimpleDelay(delay))
+    }
+
+    pub fn sudden_death(base: Duration) -> Self {
+        TimeControl::single(base, TimeBonus::None)
+    }
+
+    fn single(base: Duration, bonus: TimeBonus) -> Self {
+        TimeControl {
+            stages: vec![TimeControlStage {
+                moves: None,
+                time: base,
+                bonus,
+            }],
+        }
+    }
+
+    /// 40/90+30: 90 minutes for 40 moves, then 30 minutes for the rest, with
+    /// 30 seconds added after every move from the start
+    pub fn classical() -> Self {
+        let increment = TimeBonus::Increment(Duration::from_secs(30));
+        TimeControl {
+            stages: vec![
+                TimeControlStage {
+                    moves: Some(40),
+                    time: Duration::from_secs(90 * 60),
+                    bonus: increment,
+                },
+                TimeControlStage {
+                    moves: None,
+                    time: Duration::from_secs(30 * 60),
+                    bonus: increment,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockError {
+    /// press or pause while no clock is running
+    NotRunning,
+    /// this player ran out of time, the clock is stopped for good
+    Flagged(Color),
+}
+
+impl fmt::Display for ClockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockError::NotRunning => write!(f, "no clock is running"),
+            ClockError::Flagged(color) => write!(f, "{color:?} ran out of time"),
+        }
+    }
+}
+
+impl std::error::Error for ClockError {}
+
+/// A chess clock for two players. Call start when the game begins and press
+/// after every move, next to Board::play or Game::play.
+#[derive(Debug, Clone)]
+pub struct Clock<T: TimeSource> {
+    control: TimeControl,
+    time_source: T,
+    /// time left at the start of the current move, white then black
+    remaining: [Duration; 2],
+    stage: [usize; 2],
+    /// moves made in the current stage
+    stage_moves: [u32; 2],
+    running: Option<Color>,
+    /// whose move it is, stays set while the clock is paused
+    to_move: Option<Color>,
+    /// when the clock was last started for the current move
+    move_started: Duration,
+    /// time the current move took before the last pause, so a pause does not
+    /// hand out the delay again
+    move_elapsed: Duration,
+    flagged: Option<Color>,
+}
+
+impl<T: TimeSource> Clock<T> {
+    /// # Panics
+    /// if the time control has no stages
+    pub fn new(control: TimeControl, time_source: T) -> Self {
+        let first = control
+            .stages
+            .first()
+            .expect("time control has a stage")
+            .time;
+        Clock {
+            control,
+            time_source,
+            remaining: [first; 2],
+            stage: [0; 2],
+            stage_moves: [0; 2],
+            running: None,
+            to_move: None,
+            move_started: Duration::ZERO,
+            move_elapsed: Duration::ZERO,
+            flagged: None,
+        }
+    }
+
+    /// Starts (or resumes) counting for `color`. Starting the other player in
+    /// the middle of a paused move charges that move without any bonus.
+    pub fn start(&mut self, color: Color) -> Result<(), ClockError> {
+        if let Some(flagged) = self.check_flag() {
+            return Err(ClockError::Flagged(flagged));
+        }
+        self.pause().ok();
+        if let Some(other) = self.to_move
+            && other != color
+        {
+            let (charged, _) = self.used(other);
+            let left = &mut self.remaining[other.index()];
+            *left = left.saturating_sub(charged);
+            self.move_elapsed = Duration::ZERO;
+        }
+        self.to_move = Some(color);
+        self.running = Some(color);
+        self.move_started = self.time_source.now();
+        Ok(())
+    }
+
+    /// Stops the clock without ending the move, start resumes it
+    pub fn pause(&mut self) -> Result<(), ClockError> {
+        if let Some(flagged) = self.check_flag() {
+            return Err(ClockError::Flagged(flagged));
+        }
+        self.running.ok_or(ClockError::NotRunning)?;
+        self.move_elapsed += self.time_source.now().saturating_sub(self.move_started);
+        self.running = None;
+        Ok(())
+    }
+
+    /// None once a player is out of time, even before anything was pressed
+    pub fn running(&self) -> Option<Color> {
+        self.running.filter(|_| self.flagged().is_none())
+    }
+
+    /// Ends the running player's move, adds the bonus and starts the other clock.
+    /// Returns the player whose clock runs now.
+    pub fn press(&mut self) -> Result<Color, ClockError> {
+        if let Some(flagged) = self.check_flag() {
+            return Err(ClockError::Flagged(flagged));
+        }
+        let color = self.running.ok_or(ClockError::NotRunning)?;
+
+        let i = color.index();
+        let (charged, elapsed) = self.used(color);
+        let stage = self.current_stage(color);
+        // flagged read the time a moment earlier, so charged can be a bit more
+        // than what was left
+        self.remaining[i] = self.remaining[i].saturating_sub(charged);
+        self.remaining[i] += match stage.bonus {
+            TimeBonus::Increment(increment) => increment,
+            TimeBonus::Bronstein(delay) => elapsed.min(delay),
+            TimeBonus::None | TimeBonus::SimpleDelay(_) => Duration::ZERO,
+        };
+
+        self.stage_moves[i] += 1;
+        if stage.moves == Some(self.stage_moves[i]) {
+            self.stage[i] = (self.stage[i] + 1).min(self.control.stages.len() - 1);
+            self.stage_moves[i] = 0;
+            self.remaining[i] += self.current_stage(color).time;
+        }
+
+        let next = color.opposite();
+        self.to_move = Some(next);
+        self.running = Some(next);
+        self.move_started = self.time_source.now();
+        self.move_elapsed = Duration::ZERO;
+        Ok(next)
+    }
+
+    /// Time left for `color` right now
+    pub fn remaining(&self, color: Color) -> Duration {
+        self.remaining[color.index()].saturating_sub(self.used(color).0)
+    }
+
+    /// The player that ran out of time, if any. Checked live, no press needed.
+    pub fn flagged(&self) -> Option<Color> {
+        self.flagged.or_else(|| {
+            self.running
+                .filter(|&color| self.remaining(color).is_zero())
+        })
+    }
+
+    /// Stops the clock for good once a player is out of time, returns who
+    fn check_flag(&mut self) -> Option<Color> {
+        if self.flagged.is_none()
+            && let Some(color) = self.flagged()
+        {
+            self.remaining[color.index()] = Duration::ZERO;
+            self.running = None;
+            self.to_move = None;
+            self.flagged = Some(color);
+        }
+        self.flagged
+    }
+
+    fn current_stage(&self, color: Color) -> TimeControlStage {
+        self.control.stages[self.stage[color.index()]]
+    }
+
+    /// (time taken from the clock, time the move has taken) for the current move,
+    /// pauses left out. Zero if it is not `color`'s move.
+    fn used(&self, color: Color) -> (Duration, Duration) {
+        if self.to_move != Some(color) {
+            return (Duration::ZERO, Duration::ZERO);
+        }
+        let mut elapsed = self.move_elapsed;
+        if self.running == Some(color) {
+            elapsed += self.time_source.now().saturating_sub(self.move_started);
+        }
+        let charged = match self.current_stage(color).bonus {
+            TimeBonus::SimpleDelay(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        };
+        (charged, elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    fn clock(control: TimeControl) -> (Clock<ManualTime>, ManualTime) {
+        let time = ManualTime::default();
+        let mut clock = Clock::new(control, time.clone());
+        clock.start(Color::White).unwrap();
+        (clock, time)
+    }
+
+    #[test]
+    fn test_fischer_increment() {
+        let (mut clock, time) = clock(TimeControl::fischer(secs(180), secs(2)));
+        time.advance(secs(10));
+        assert_eq!(clock.remaining(Color::White), secs(170));
+        assert_eq!(clock.press(), Ok(Color::Black));
+        assert_eq!(clock.remaining(Color::White), secs(172));
+
+        time.advance(secs(1));
+        clock.press().unwrap();
+        assert_eq!(clock.remaining(Color::Black), secs(181));
+    }
+
+    #[test]
+    fn test_bronstein_delay() {
+        let (mut clock, time) = clock(TimeControl::bronstein(secs(60), secs(5)));
+        time.advance(secs(3));
+        assert_eq!(clock.remaining(Color::White), secs(57));
+        clock.press().unwrap();
+        // all 3 seconds come back, but never more than the delay
+        assert_eq!(clock.remaining(Color::White), secs(60));
+
+        time.advance(secs(8));
+        clock.press().unwrap();
+        assert_eq!(clock.remaining(Color::Black), secs(57));
+    }
+
+    #[test]
+    fn test_simple_delay() {
+        let (mut clock, time) = clock(TimeControl::simple_delay(secs(60), secs(5)));
+        time.advance(secs(4));
+        assert_eq!(clock.remaining(Color::White), secs(60));
+        time.advance(secs(3));
+        assert_eq!(clock.remaining(Color::White), secs(58));
+        clock.press().unwrap();
+        assert_eq!(clock.remaining(Color::White), secs(58));
+    }
+
+    #[test]
+    fn test_pause_keeps_the_used_delay() {
+        let (mut clock, time) = clock(TimeControl::simple_delay(secs(60), secs(5)));
+        time.advance(secs(4));
+        clock.pause().unwrap();
+        time.advance(secs(30));
+        clock.start(Color::White).unwrap();
+        // 4 of the 5 seconds were used before the pause
+        time.advance(secs(4));
+        assert_eq!(clock.remaining(Color::White), secs(57));
+        clock.press().unwrap();
+        assert_eq!(clock.remaining(Color::White), secs(57));
+    }
+
+    #[test]
+    fn test_pause_keeps_the_bronstein_time() {
+        let (mut clock, time) = clock(TimeControl::bronstein(secs(60), secs(5)));
+        time.advance(secs(3));
+        clock.pause().unwrap();
+        clock.start(Color::White).unwrap();
+        time.advance(secs(3));
+        clock.press().unwrap();
+        // 6 seconds used, only 5 come back
+        assert_eq!(clock.remaining(Color::White), secs(59));
+    }
+
+    #[test]
+    fn test_multi_stage_control() {
+        let (mut clock, time) = clock(TimeControl::classical());
+        for _ in 0..39 {
+            time.advance(secs(60));
+            clock.press().unwrap();
+            clock.press().unwrap();
+        }
+        // 90 minutes - 39 minutes + 39 * 30 seconds
+        assert_eq!(clock.remaining(Color::White), secs(51 * 60 + 39 * 30));
+
+        time.advance(secs(60));
+        clock.press().unwrap();
+        // the 40th move brings the 30 minutes of the second stage
+        assert_eq!(
+            clock.remaining(Color::White),
+            secs(50 * 60 + 40 * 30 + 30 * 60)
+        );
+        // black moved instantly and only got the increments
+        assert_eq!(clock.remaining(Color::Black), secs(90 * 60 + 39 * 30));
+    }
+
+    #[test]
+    fn test_flag_and_pause() {
+        let (mut clock, time) = clock(TimeControl::sudden_death(secs(60)));
+        time.advance(secs(20));
+        clock.pause().unwrap();
+        time.advance(secs(100));
+        assert_eq!(clock.remaining(Color::White), secs(40));
+        assert_eq!(clock.press(), Err(ClockError::NotRunning));
+
+        clock.start(Color::White).unwrap();
+        time.advance(secs(40));
+        assert_eq!(clock.flagged(), Some(Color::White));
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.press(), Err(ClockError::Flagged(Color::White)));
+        assert_eq!(
+            clock.start(Color::Black),
+            Err(ClockError::Flagged(Color::White))
+        );
+    }
+}
@@ -0,0 +1,476 @@
+use std::fmt;
+
+use crate::board::{BOARD_COLS, BOARD_ROWS, Board, CastlingRights, Position};
+use crate::pieces::{Color, Piece, PieceType};
+
+/// Everything that can be wrong with a FEN string. Ranks are numbered like on a
+/// chess board, so the first rank in the FEN string is rank 8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    Empty,
+    TooManyFields,
+    InvalidCharacter { rank: u8, ch: char },
+    RankTooLong { rank: u8 },
+    RankTooShort { rank: u8 },
+    WrongRankCount { found: usize },
+    PawnOnBackRank { rank: u8 },
+    MissingKing(Color),
+    TooManyKings(Color),
+    OpponentInCheck,
+    InvalidSideToMove(String),
+    InvalidCastling { ch: char },
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::Empty => write!(f, "empty FEN string"),
+            FenError::TooManyFields => write!(f, "FEN has more than six fields"),
+            FenError::InvalidCharacter { rank, ch } => {
+                write!(f, "invalid character '{ch}' on rank {rank}")
+            }
+            FenError::RankTooLong { rank } => write!(f, "rank {rank} has more than 8 squares"),
+            FenError::RankTooShort { rank } => write!(f, "rank {rank} has less than 8 squares"),
+            FenError::WrongRankCount { found } => write!(f, "expected 8 ranks, found {found}"),
+            FenError::PawnOnBackRank { rank } => write!(f, "pawn on rank {rank}"),
+            FenError::MissingKing(color) => write!(f, "no {color:?} king"),
+            FenError::TooManyKings(color) => write!(f, "more than one {color:?} king"),
+            FenError::OpponentInCheck => write!(f, "side not to move is in check"),
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{s}'"),
+            FenError::InvalidCastling { ch } => write!(f, "invalid castling character '{ch}'"),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{s}'"),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock '{s}'"),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number '{s}'"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl Board {
+    /// Parses a FEN string into a new board.
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let mut board = Board::default();
+        board.setup_fen(fen)?;
+        Ok(board)
+    }
+
+    /// Sets up the board from a FEN string. All six fields are read, but only the
+    /// piece placement is required, missing fields get these defaults:
+    /// white to move, castling inferred from kings and rooks on their home squares,
+    /// no en passant, halfmove clock 0 and fullmove number 1.
+    /// Castling rights without a king and rook on their home squares are dropped.
+    /// On error the board is left untouched.
+    pub fn setup_fen(&mut self, fen: &str) -> Result<(), FenError> {
+        let mut board = Board::default();
+
+        let mut parts = fen.split_whitespace();
+        let board_part = parts.next().ok_or(FenError::Empty)?;
+        board.parse_placement(board_part)?;
+
+        board.move_turn = match parts.next() {
+            None | Some("w") => Color::White,
+            Some("b") => Color::Black,
+            Some(other) => return Err(FenError::InvalidSideToMove(other.to_string())),
+        };
+
+        board.castling = match parts.next() {
+            None => CastlingRights::all(),
+            Some("-") => CastlingRights::none(),
+            Some(field) => parse_castling(field)?,
+        };
+        board.castling = board.possible_castling();
+
+        board.en_passant = match parts.next() {
+            None | Some("-") => None,
+            Some(field) => Some(board.parse_en_passant(field)?),
+        };
+
+        if let Some(field) = parts.next() {
+            board.halfmove_clock = field
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(field.to_string()))?;
+        }
+        if let Some(field) = parts.next() {
+            board.fullmove_number = match field.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(FenError::InvalidFullmoveNumber(field.to_string())),
+            };
+        }
+
+        if parts.next().is_some() {
+            return Err(FenError::TooManyFields);
+        }
+        board.zobrist = board.compute_zobrist();
+
+        // the king of the side that just moved cant be attacked
+        board.move_turn = board.move_turn.opposite();
+        let king_pos = match board.move_turn {
+            Color::White => board.white_king,
+            Color::Black => board.black_king,
+        }
+        .expect("validated: both kings placed");
+        let opponent_in_check = board.is_square_attacked(king_pos);
+        board.move_turn = board.move_turn.opposite();
+        if opponent_in_check {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        *self = board;
+        Ok(())
+    }
+
+    /// Writes the board as a six field FEN string
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        for row in (0..BOARD_ROWS).rev() {
+            let mut empty = 0;
+            for col in 0..BOARD_COLS {
+                match self.get(Position { row, col }) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece_to_char(piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if row > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push(' ');
+        fen.push(match self.move_turn {
+            Color::White => 'w',
+            Color::Black => 'b',
+        });
+
+        fen.push(' ');
+        let rights = self.possible_castling();
+        let castling: String = [
+            (rights.white_king_side, 'K'),
+            (rights.white_queen_side, 'Q'),
+            (rights.black_king_side, 'k'),
+            (rights.black_queen_side, 'q'),
+        ]
+        .iter()
+        .filter(|(has_right, _)| *has_right)
+        .map(|(_, ch)| *ch)
+        .collect();
+        if castling.is_empty() {
+            fen.push('-');
+        } else {
+            fen.push_str(&castling);
+        }
+
+        fen.push(' ');
+        match self.en_passant {
+            Some(pos) => fen.push_str(&pos.to_string()),
+            None => fen.push('-'),
+        }
+
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+
+    fn parse_placement(&mut self, placement: &str) -> Result<(), FenError> {
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != BOARD_ROWS as usize {
+            return Err(FenError::WrongRankCount { found: ranks.len() });
+        }
+
+        let mut white_kings = 0;
+        let mut black_kings = 0;
+
+        for (fen_row, rank_str) in ranks.iter().enumerate() {
+            // Flip the row so that FEN's row 0 becomes board's row 7
+            let row = BOARD_ROWS - 1 - fen_row as i8;
+            let rank = row as u8 + 1;
+            let mut col = 0;
+
+            for ch in rank_str.chars() {
+                if let Some(empty) = ch.to_digit(10).filter(|d| (1..=8).contains(d)) {
+                    col += empty as i8;
+                    if col > BOARD_COLS {
+                        return Err(FenError::RankTooLong { rank });
+                    }
+                    continue;
+                }
+
+                let piece = piece_from_char(ch).ok_or(FenError::InvalidCharacter { rank, ch })?;
+                if col >= BOARD_COLS {
+                    return Err(FenError::RankTooLong { rank });
+                }
+                if piece.piece_type == PieceType::Pawn && (row == 0 || row == BOARD_ROWS - 1) {
+                    return Err(FenError::PawnOnBackRank { rank });
+                }
+                if piece.piece_type == PieceType::King {
+                    match piece.color {
+                        Color::White => white_kings += 1,
+                        Color::Black => black_kings += 1,
+                    }
+                }
+
+                self.set(Position { row, col }, Some(piece));
+                col += 1;
+            }
+
+            if col < BOARD_COLS {
+                return Err(FenError::RankTooShort { rank });
+            }
+        }
+
+        for (count, color) in [(white_kings, Color::White), (black_kings, Color::Black)] {
+            match count {
+                0 => return Err(FenError::MissingKing(color)),
+                1 => {}
+                _ => return Err(FenError::TooManyKings(color)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The en passant square has to be behind a pawn that just made a double push
+    fn parse_en_passant(&self, field: &str) -> Result<Position, FenError> {
+        let invalid = || FenError::InvalidEnPassant(field.to_string());
+        let pos: Position = field.parse().map_err(|_| invalid())?;
+
+        let (expected_row, pawn_row, pawn_color) = match self.move_turn {
+            Color::White => (5, 4, Color::Black),
+            Color::Black => (2, 3, Color::White),
+        };
+        let pawn_in_place = self
+            .get(Position {
+                row: pawn_row,
+                col: pos.col,
+            })
+            .is_some_and(|p| p.piece_type == PieceType::Pawn && p.color == pawn_color);
+
+        if pos.row != expected_row || !pawn_in_place || self.get(pos).is_some() {
+            return Err(invalid());
+        }
+        Ok(pos)
+    }
+
+    /// The stored castling rights, minus the ones whose king or rook is not on its
+    /// home square anymore
+    pub fn possible_castling(&self) -> CastlingRights {
+        let is_home = |row: i8, col: i8, piece_type: PieceType, color: Color| {
+            self.get(Position { row, col })
+                .is_some_and(|p| p.piece_type == piece_type && p.color == color)
+        };
+
+        let mut rights = self.castling;
+        if !is_home(0, 4, PieceType::King, Color::White) {
+            rights.remove_color(Color::White);
+        }
+        if !is_home(7, 4, PieceType::King, Color::Black) {
+            rights.remove_color(Color::Black);
+        }
+        for (row, col, color) in [
+            (0, 0, Color::White),
+            (0, 7, Color::White),
+            (7, 0, Color::Black),
+            (7, 7, Color::Black),
+        ] {
+            if !is_home(row, col, PieceType::Rook, color) {
+                rights.remove_corner(Position { row, col });
+            }
+        }
+        rights
+    }
+}
+
+fn piece_from_char(ch: char) -> Option<Piece> {
+    let color = if ch.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    Some(Piece::new(PieceType::from_letter(ch)?, color))
+}
+
+fn piece_to_char(piece: Piece) -> char {
+    let ch = piece.piece_type.letter();
+    match piece.color {
+        Color::White => ch,
+        Color::Black => ch.to_ascii_lowercase(),
+    }
+}
+
+fn parse_castling(field: &str) -> Result<CastlingRights, FenError> {
+    let mut rights = CastlingRights::none();
+    for ch in field.chars() {
+        let right = match ch {
+            'K' => &mut rights.white_king_side,
+            'Q' => &mut rights.white_queen_side,
+            'k' => &mut rights.black_king_side,
+            'q' => &mut rights.black_queen_side,
+            _ => return Err(FenError::InvalidCastling { ch }),
+        };
+        if *right {
+            return Err(FenError::InvalidCastling { ch });
+        }
+        *right = true;
+    }
+    Ok(rights)
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_fen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup_fen_all_fields() {
+        let mut board = Board::default();
+        board
+            .setup_fen("rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w Kq c6 0 2")
+            .unwrap();
+
+        assert_eq!(board.move_turn, Color::White);
+        assert_eq!(board.en_passant, Some(Position { row: 5, col: 2 }));
+        assert_eq!(board.halfmove_clock, 0);
+        assert_eq!(board.fullmove_number, 2);
+        assert_eq!(
+            board.castling,
+            CastlingRights {
+                white_king_side: true,
+                white_queen_side: false,
+                black_king_side: false,
+                black_queen_side: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_setup_fen_black_to_move() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 12 40").unwrap();
+
+        assert_eq!(board.move_turn, Color::Black);
+        assert_eq!(board.castling, CastlingRights::none());
+        assert_eq!(board.en_passant, None);
+        assert_eq!(board.halfmove_clock, 12);
+        assert_eq!(board.fullmove_number, 40);
+    }
+
+    #[test]
+    fn test_setup_fen_placement_only_infers_castling() {
+        let board = Board::from_fen("r3k3/8/8/8/8/8/8/4K2R").unwrap();
+
+        assert_eq!(
+            board.castling,
+            CastlingRights {
+                white_king_side: true,
+                white_queen_side: false,
+                black_king_side: false,
+                black_queen_side: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_fen_errors() {
+        let cases = [
+            ("", FenError::Empty),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 1 extra",
+                FenError::TooManyFields,
+            ),
+            (
+                "4k3/8/8/8/3x4/8/8/4K3",
+                FenError::InvalidCharacter { rank: 4, ch: 'x' },
+            ),
+            ("4k3/8/8/8/8/8/8/4K3p", FenError::RankTooLong { rank: 1 }),
+            ("4k3/8/8/8/8/8/8/4K4", FenError::RankTooLong { rank: 1 }),
+            ("4k3/8/8/7/8/8/8/4K3", FenError::RankTooShort { rank: 5 }),
+            ("4k3/8/8/8/8/8/4K3", FenError::WrongRankCount { found: 7 }),
+            ("4k2P/8/8/8/8/8/8/4K3", FenError::PawnOnBackRank { rank: 8 }),
+            ("8/8/8/8/8/8/8/4K3", FenError::MissingKing(Color::Black)),
+            ("4k3/8/8/8/8/8/8/3KK3", FenError::TooManyKings(Color::White)),
+            ("4k3/8/8/8/8/8/8/4R1K1 w", FenError::OpponentInCheck),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x",
+                FenError::InvalidSideToMove("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w KX",
+                FenError::InvalidCastling { ch: 'X' },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e3",
+                FenError::InvalidEnPassant("e3".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x",
+                FenError::InvalidHalfmoveClock("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+                FenError::InvalidFullmoveNumber("0".to_string()),
+            ),
+        ];
+
+        for (fen, expected) in cases {
+            assert_eq!(Board::from_fen(fen).err(), Some(expected), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_setup_fen_error_leaves_board_untouched() {
+        let mut board = Board::start_pos();
+        assert!(board.setup_fen("8/8/8/8/8/8/8/8").is_err());
+        assert!(board.get(Position { row: 0, col: 4 }).is_some());
+    }
+
+    #[test]
+    fn test_to_fen_start_pos() {
+        assert_eq!(
+            Board::start_pos().to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_to_fen_after_moves() {
+        let mut board = Board::start_pos();
+        board.play((1, 4), (3, 4), None).unwrap();
+        assert_eq!(
+            board.to_string(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        board.play((7, 6), (5, 5), None).unwrap();
+        board.play((0, 4), (1, 4), None).unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"
+        );
+    }
+
+    #[test]
+    fn test_to_fen_drops_castling_without_rook() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        board.set(Position { row: 7, col: 7 }, None);
+        assert_eq!(board.to_fen(), "r3k3/8/8/8/8/8/8/R3K2R w KQq - 0 1");
+    }
+}
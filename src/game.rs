@@ -0,0 +1,306 @@
+use std::fmt;
+
+use crate::board::{Board, Position};
+use crate::moves::Move;
+use crate::pgn::{PgnGame, PgnResult};
+use crate::pieces::{Color, PieceType};
+use crate::rules::{GameResult, MoveError, MoveOk};
+
+/// Why a Game refused to do something
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameError {
+    /// the game already ended, nothing can be changed anymore
+    GameOver(GameResult),
+    Illegal(MoveError),
+    /// accept_draw without an offer from the other player
+    NoDrawOffer,
+    /// claim_draw when there is no fifty move rule or threefold repetition
+    NothingToClaim,
+    /// undo before any move was played
+    NoMoves,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::GameOver(result) => write!(f, "game is over: {result:?}"),
+            GameError::Illegal(e) => write!(f, "illegal move: {e:?}"),
+            GameError::NoDrawOffer => write!(f, "no draw offer to accept"),
+            GameError::NothingToClaim => write!(f, "no draw to claim"),
+            GameError::NoMoves => write!(f, "no moves to undo"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<MoveError> for GameError {
+    fn from(e: MoveError) -> Self {
+        GameError::Illegal(e)
+    }
+}
+
+/// A game on top of a Board, keeps the result, draw offers and claims.
+/// Once the game has ended every change returns GameError::GameOver.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Game {
+    board: Board,
+    result: Option<GameResult>,
+    /// the player whose draw offer is open
+    draw_offer: Option<Color>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game::from_board(Board::start_pos())
+    }
+
+    /// Starts from any position, the history of the board is kept so it can be undone
+    pub fn from_board(board: Board) -> Self {
+        let result = board.game_over().filter(|result| !result.is_claimable());
+        Game {
+            board,
+            result,
+            draw_offer: None,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// None while the game is still going
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// Every move played so far, oldest first
+    pub fn moves(&self) -> Vec<Move> {
+        self.board.history.iter().map(|undo| undo.mv).collect()
+    }
+
+    pub fn draw_offer(&self) -> Option<Color> {
+        self.draw_offer
+    }
+
+    fn check_not_over(&self) -> Result<(), GameError> {
+        match self.result {
+            Some(result) => Err(GameError::GameOver(result)),
+            None => Ok(()),
+        }
+    }
+
+    /// Same as Board::move_piece, ends the game on checkmate and the draws
+    /// that do not have to be claimed
+    pub fn play(
+        &mut self,
+        from_pos: Position,
+        to_pos: Position,
+        prom_piece_type: Option<PieceType>,
+    ) -> Result<MoveOk, GameError> {
+        self.check_not_over()?;
+        let mover = self.board.move_turn;
+        let move_ok = self.board.move_piece(from_pos, to_pos, prom_piece_type)?;
+        if move_ok == MoveOk::NeedsPromotion {
+            return Ok(move_ok);
+        }
+
+        // moving instead of accepting turns the offer down
+        if self.draw_offer.is_some_and(|color| color != mover) {
+            self.draw_offer = None;
+        }
+        self.result = self
+            .board
+            .game_over()
+            .filter(|result| !result.is_claimable());
+        Ok(MoveOk::Done)
+    }
+
+    /// Plays a move from legal_moves
+    pub fn play_move(&mut self, mv: Move) -> Result<MoveOk, GameError> {
+        self.play(mv.from, mv.to, mv.promotion)
+    }
+
+    /// Takes back the last move, only while the game is still going
+    pub fn undo(&mut self) -> Result<Move, GameError> {
+        self.check_not_over()?;
+        let undo = self.board.unmake_move().ok_or(GameError::NoMoves)?;
+        self.draw_offer = None;
+        Ok(undo.mv)
+    }
+
+    pub fn resign(&mut self, color: Color) -> Result<GameResult, GameError> {
+        self.check_not_over()?;
+        let result = GameResult::Resignation(color.opposite());
+        self.result = Some(result);
+        Ok(result)
+    }
+
+    /// The offer stays open until the other player accepts it or makes a move
+    pub fn offer_draw(&mut self, color: Color) -> Result<(), GameError> {
+        self.check_not_over()?;
+        self.draw_offer = Some(color);
+        Ok(())
+    }
+
+    pub fn accept_draw(&mut self, color: Color) -> Result<GameResult, GameError> {
+        self.check_not_over()?;
+        if self.draw_offer != Some(color.opposite()) {
+            return Err(GameError::NoDrawOffer);
+        }
+        self.draw_offer = None;
+        self.result = Some(GameResult::DrawAgreed);
+        Ok(GameResult::DrawAgreed)
+    }
+
+    /// `color` ran out of time. A loss, unless the other side could never mate
+    pub fn time_out(&mut self, color: Color) -> Result<GameResult, GameError> {
+        self.check_not_over()?;
+        let result = if self.board.can_mate(color.opposite()) {
+            GameResult::Timeout(color.opposite())
+        } else {
+            GameResult::DrawOnTime
+        };
+        self.result = Some(result);
+        Ok(result)
+    }
+
+    /// Ends the game on the fifty move rule or threefold repetition
+    pub fn claim_draw(&mut self) -> Result<GameResult, GameError> {
+        self.check_not_over()?;
+        match self.board.game_over() {
+            Some(result) if result.is_claimable() => {
+                self.result = Some(result);
+                Ok(result)
+            }
+            _ => Err(GameError::NothingToClaim),
+        }
+    }
+
+    /// The game as a PGN record, with the result of this game
+    pub fn to_pgn_game(&self) -> PgnGame {
+        let mut pgn_game = PgnGame::from_board(&self.board);
+        pgn_game.result = self.result.map_or(PgnResult::Ongoing, PgnResult::from);
+        pgn_game
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play_sans(game: &mut Game, sans: &str) {
+        for san in sans.split_whitespace() {
+            let mv = game.board().parse_san(san).unwrap();
+            game.play_move(mv).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_checkmate_freezes_game() {
+        let mut game = Game::new();
+        play_sans(&mut game, "f3 e5 g4 Qh4#");
+        assert_eq!(game.result(), Some(GameResult::Checkmate(Color::Black)));
+        assert_eq!(game.moves().len(), 4);
+
+        let over = GameError::GameOver(GameResult::Checkmate(Color::Black));
+        assert_eq!(game.play(Position::A2, Position::A3, None), Err(over));
+        assert_eq!(game.undo(), Err(over));
+        assert_eq!(game.to_pgn_game().result, PgnResult::BlackWins);
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut game = Game::new();
+        assert_eq!(game.undo(), Err(GameError::NoMoves));
+        play_sans(&mut game, "e4");
+        let mv = game.undo().unwrap();
+        assert_eq!(mv.from, Position::E2);
+        assert_eq!(game.board(), &Board::start_pos());
+        assert_eq!(
+            game.play(Position::E2, Position::E5, None),
+            Err(GameError::Illegal(MoveError::IllegalShape))
+        );
+    }
+
+    #[test]
+    fn test_resign() {
+        let mut game = Game::new();
+        assert_eq!(
+            game.resign(Color::White),
+            Ok(GameResult::Resignation(Color::Black))
+        );
+        assert!(game.is_over());
+        assert_eq!(
+            game.resign(Color::Black),
+            Err(GameError::GameOver(GameResult::Resignation(Color::Black)))
+        );
+    }
+
+    #[test]
+    fn test_draw_offers() {
+        let mut game = Game::new();
+        game.offer_draw(Color::White).unwrap();
+        assert_eq!(game.accept_draw(Color::White), Err(GameError::NoDrawOffer));
+
+        // the offer can be made with a move, and a move by the other side declines it
+        play_sans(&mut game, "e4");
+        assert_eq!(game.draw_offer(), Some(Color::White));
+        play_sans(&mut game, "e5");
+        assert_eq!(game.draw_offer(), None);
+        assert_eq!(game.accept_draw(Color::Black), Err(GameError::NoDrawOffer));
+
+        game.offer_draw(Color::White).unwrap();
+        assert_eq!(game.accept_draw(Color::Black), Ok(GameResult::DrawAgreed));
+        assert_eq!(game.to_pgn_game().result, PgnResult::Draw);
+    }
+
+    #[test]
+    fn test_claim_draw() {
+        let mut game = Game::new();
+        assert_eq!(game.claim_draw(), Err(GameError::NothingToClaim));
+        play_sans(&mut game, "Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1 Ng8");
+        // threefold repetition does not end the game by itself
+        assert!(!game.is_over());
+        assert_eq!(game.claim_draw(), Ok(GameResult::ThreefoldRepetition));
+        assert!(game.is_over());
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        let mut game = Game::from_board(board);
+        play_sans(&mut game, "Ra2");
+        assert_eq!(game.claim_draw(), Ok(GameResult::FiftyMoveRule));
+    }
+
+    #[test]
+    fn test_time_out() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1").unwrap();
+        let mut game = Game::from_board(board.clone());
+        assert_eq!(
+            game.time_out(Color::Black),
+            Ok(GameResult::Timeout(Color::White))
+        );
+
+        // a lone king can not mate, so white running out of time is a draw
+        let mut game = Game::from_board(board);
+        assert_eq!(game.time_out(Color::White), Ok(GameResult::DrawOnTime));
+        assert_eq!(game.to_pgn_game().result, PgnResult::Draw);
+    }
+
+    #[test]
+    fn test_automatic_draws_end_game() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/3qK3 w - - 0 1").unwrap();
+        let mut game = Game::from_board(board);
+        play_sans(&mut game, "Kxd1");
+        assert_eq!(game.result(), Some(GameResult::InsufficientMaterial));
+    }
+}
@@ -1,19 +1,44 @@
+pub mod bitboard;
 pub mod board;
+pub mod clock;
+pub mod fen;
+pub mod game;
+mod magic;
 pub mod movegen;
+pub mod moves;
+pub mod pgn;
 pub mod pieces;
 pub mod rules;
+pub mod san;
+pub mod uci;
+pub mod zobrist;
 
-pub use board::{BOARD_COLS, BOARD_ROWS, Board, Position};
+pub use bitboard::Bitboard;
+pub use board::{
+    BOARD_COLS, BOARD_ROWS, Board, CastleSide, CastlingRights, ParseSquareError, Position,
+    PositionKey,
+};
+pub use clock::{
+    Clock, ClockError, ManualTime, RealTime, TimeBonus, TimeControl, TimeControlStage, TimeSource,
+};
+pub use fen::FenError;
+pub use game::{Game, GameError};
+pub use movegen::PerftStats;
+pub use moves::Move;
+pub use pgn::{PgnError, PgnGame, PgnMove, PgnReader, PgnResult};
 pub use pieces::{Color, Piece, PieceType};
-pub use rules::{GameResult, MoveError, MoveOk};
+pub use rules::{
+    CheckInfo, FIFTY_MOVE_PLIES, GameResult, MoveError, MoveOk, MoveType, SEVENTY_FIVE_MOVE_PLIES,
+    Undo,
+};
+pub use san::SanError;
+pub use uci::{UciError, UciMove};
 
 pub const STARTING_BOARD: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
 
 impl Board {
     pub fn start_pos() -> Self {
-        let mut board = Board::default();
-        board.setup_fen(STARTING_BOARD);
-        board
+        Board::from_fen(STARTING_BOARD).expect("validated: starting FEN")
     }
 
     pub fn play(
@@ -33,7 +58,7 @@ impl Board {
         self.move_piece(from_pos, to_pos, prom_piece_type)
     }
 
-    pub fn legal_moves(&self) -> Vec<(Position, Position, Option<PieceType>)> {
+    pub fn legal_moves(&self) -> Vec<Move> {
         movegen::all_legal_moves(self)
     }
 
@@ -41,6 +66,18 @@ impl Board {
         movegen::perft_layers(self, depth)
     }
 
+    pub fn perft_layers_parallel(&self, depth: usize, threads: usize) -> Vec<usize> {
+        movegen::perft_layers_parallel(self, depth, threads)
+    }
+
+    pub fn perft_divide(&mut self, depth: usize) -> Vec<(Move, usize)> {
+        movegen::perft_divide(self, depth)
+    }
+
+    pub fn perft_stats(&mut self, depth: usize) -> Vec<PerftStats> {
+        movegen::perft_stats(self, depth)
+    }
+
     pub fn game_over(&self) -> Option<GameResult> {
         if self.is_check_mate() {
             Some(GameResult::Checkmate(match self.move_turn {
@@ -49,8 +86,26 @@ impl Board {
             }))
         } else if self.is_stale_mate() {
             Some(GameResult::Stalemate)
+        } else if self.is_insufficient_material() {
+            Some(GameResult::InsufficientMaterial)
+        } else if self.halfmove_clock >= SEVENTY_FIVE_MOVE_PLIES {
+            Some(GameResult::SeventyFiveMoveRule)
         } else {
-            None
+            // both repetition and the fifty move rule need at least 8 quiet plies
+            let repetitions = if self.halfmove_clock >= 8 {
+                self.repetition_count()
+            } else {
+                1
+            };
+            if repetitions >= 5 {
+                Some(GameResult::FivefoldRepetition)
+            } else if repetitions >= 3 {
+                Some(GameResult::ThreefoldRepetition)
+            } else if self.halfmove_clock >= FIFTY_MOVE_PLIES {
+                Some(GameResult::FiftyMoveRule)
+            } else {
+                None
+            }
         }
     }
 }
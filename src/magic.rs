@@ -0,0 +1,286 @@
+use std::sync::OnceLock;
+
+use crate::bitboard::{BISHOP_DIRECTIONS, Bitboard, ROOK_DIRECTIONS, sliding_attacks};
+
+// Found once with a random search over sparse 64 bit numbers. Any number works
+// as long as two occupancies only share a slot when they give the same attacks,
+// building the tables checks that.
+const ROOK_MAGICS: [u64; 64] = [
+    0x2080_0020_8040_0010,
+    0x00C0_0020_0140_1000,
+    0x2100_1100_0840_2002,
+    0x0880_0800_8104_1000,
+    0x0200_0200_2004_1008,
+    0x2300_0400_0801_0012,
+    0x0C00_2830_0400_8201,
+    0x0180_0100_0040_7A80,
+    0x0168_8000_8040_0020,
+    0x0010_4000_4020_1000,
+    0x1001_0020_0100_1048,
+    0x1001_0024_0810_0100,
+    0x0801_0004_0801_0012,
+    0x4001_0002_0900_0400,
+    0x08A2_0004_C802_0001,
+    0x2002_8011_4500_2280,
+    0x0080_8600_2100_4200,
+    0x0010_00C0_0940_2002,
+    0x00B0_0020_0400_2800,
+    0x100A_8080_1002_0800,
+    0x9400_8080_0400_0800,
+    0x0090_8080_0400_0200,
+    0x0000_0400_1081_0208,
+    0x2000_0200_0044_8534,
+    0x4104_4004_8000_8033,
+    0x0000_8101_0020_4000,
+    0x0440_4309_0020_0010,
+    0x4600_2409_0010_0100,
+    0x0804_0801_0011_0004,
+    0x0001_0003_0008_0400,
+    0x0004_0844_0001_1002,
+    0x0023_0402_0000_8041,
+    0x0580_0500_4300_2080,
+    0x0400_8040_0280_2008,
+    0x0001_0020_0100_4010,
+    0x0080_200A_0200_1040,
+    0x600D_4802_8080_2400,
+    0x400B_8002_0180_0C00,
+    0x2408_2110_0400_4208,
+    0x0200_2110_8200_0844,
+    0x0020_8040_1020_8000,
+    0x5030_0040_2010_4000,
+    0xA042_0840_8022_0010,
+    0x4088_0800_1000_8080,
+    0x5002_0801_0011_0004,
+    0x2012_0020_1004_0400,
+    0x0040_3182_1044_0008,
+    0x0120_9410_4082_0001,
+    0x1000_8001_0040_2100,
+    0x0040_0020_1000_4840,
+    0x8108_4500_2000_1900,
+    0x0200_2040_0812_0200,
+    0x0080_800C_0018_0180,
+    0x0885_0004_0042_0900,
+    0x2308_0201_1008_C400,
+    0x3801_7408_9143_2200,
+    0x0A00_2502_1202_4082,
+    0x0000_8820_4000_1105,
+    0x0042_1020_8200_0A42,
+    0xC401_2108_1000_0501,
+    0x0241_0010_0248_0005,
+    0x0081_0004_0088_0241,
+    0x0000_0090_0802_4124,
+    0x0048_1229_8041_0402,
+];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0x1862_2210_0622_0044,
+    0x2104_A142_0202_0060,
+    0x2804_0812_2044_4001,
+    0x2102_4089_0001_0001,
+    0x0002_0210_0004_0002,
+    0x08C3_1008_0500_4300,
+    0x1084_0401_2492_0050,
+    0x8900_4400_4338_2010,
+    0x2401_4108_0214_0040,
+    0x0901_2004_5420_8020,
+    0x0000_0902_1602_0541,
+    0x1283_8440_4080_0804,
+    0x0521_8404_2000_0803,
+    0x0800_0104_0240_0C40,
+    0x0000_408E_1010_0404,
+    0x0009_8100_4842_0800,
+    0x2004_2110_0428_6808,
+    0x1308_0A10_0138_0080,
+    0x0008_8010_0422_0020,
+    0x0024_0008_0248_0800,
+    0x1461_0011_9040_0401,
+    0x0020_4002_0050_0440,
+    0x0003_0004_0901_9000,
+    0x000C_2082_0D01_1802,
+    0x0008_0400_2164_100C,
+    0x0004_8400_A001_1404,
+    0x5018_1103_0804_4100,
+    0x0048_A008_0401_0020,
+    0x0007_8400_0080_2000,
+    0x8808_A200_7500_4220,
+    0x8014_0400_0082_2100,
+    0x110C_0300_0E25_1101,
+    0x0081_0948_2020_2010,
+    0x0008_0410_0004_4100,
+    0x00C1_2028_0894_0800,
+    0x8108_1008_2104_0400,
+    0x1240_0100_1001_0041,
+    0x0810_0040_8001_1000,
+    0x00A2_0C04_0180_4A00,
+    0x4001_4C00_2005_0500,
+    0x5805_0820_1204_2480,
+    0x2004_0221_4403_1000,
+    0x2082_0020_2420_4808,
+    0x0800_0042_0080_0800,
+    0x0410_0202_0410_0A02,
+    0x80C1_2040_8080_4101,
+    0x0010_104E_0180_0042,
+    0x0008_0081_0C40_0208,
+    0x1000_80B0_0820_1210,
+    0x8000_4406_0511_2101,
+    0x0002_0200_8C44_0040,
+    0x9004_0022_1044_2200,
+    0x2032_0140_8822_2045,
+    0x0C00_2022_22C2_0000,
+    0x0140_0408_20A5_0100,
+    0x0222_104C_2902_4018,
+    0x0200_1101_2120_2004,
+    0x0800_1042_00B0_0802,
+    0x0000_4014_2402_0801,
+    0x4000_0000_0420_8840,
+    0x0802_E000_4010_4100,
+    0x0300_0020_A042_4080,
+    0x0011_C004_0818_8121,
+    0x0848_0208_2204_0013,
+];
+
+/// Everything needed to look up the attacks of one slider on one square:
+/// `(occupied & mask) * magic >> shift` is the index into its part of the table
+#[derive(Debug, Clone, Copy, Default)]
+struct Magic {
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct MagicTables {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    attacks: Vec<Bitboard>,
+}
+
+static TABLES: OnceLock<MagicTables> = OnceLock::new();
+
+/// Built the first time a slider attack is looked up
+fn tables() -> &'static MagicTables {
+    TABLES.get_or_init(MagicTables::new)
+}
+
+pub fn rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.attacks[tables.rook[square].index(occupied)]
+}
+
+pub fn bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.attacks[tables.bishop[square].index(occupied)]
+}
+
+/// The squares whose occupancy matters for a slider on `square`. The last
+/// square of each ray is left out, the attack reaches it either way.
+fn relevant_mask(square: usize, directions: &[(i8, i8)]) -> Bitboard {
+    let mut mask = 0;
+    for &(row_offset, col_offset) in directions {
+        let mut row = (square / 8) as i8 + row_offset;
+        let mut col = (square % 8) as i8 + col_offset;
+        let on_board = |row: i8, col: i8| (0..8).contains(&row) && (0..8).contains(&col);
+        while on_board(row + row_offset, col + col_offset) {
+            mask |= 1 << (row * 8 + col);
+            row += row_offset;
+            col += col_offset;
+        }
+    }
+    mask
+}
+
+/// Every subset of mask, the empty one first
+fn subsets(mask: Bitboard) -> impl Iterator<Item = Bitboard> {
+    let mut next: Option<Bitboard> = Some(0);
+    std::iter::from_fn(move || {
+        let subset = next?;
+        let following = subset.wrapping_sub(mask) & mask;
+        next = (following != 0).then_some(following);
+        Some(subset)
+    })
+}
+
+impl MagicTables {
+    fn new() -> Self {
+        let mut attacks = Vec::new();
+        let mut rook = [Magic::default(); 64];
+        let mut bishop = [Magic::default(); 64];
+        for square in 0..64 {
+            rook[square] = fill(square, ROOK_MAGICS[square], &ROOK_DIRECTIONS, &mut attacks);
+            bishop[square] = fill(
+                square,
+                BISHOP_MAGICS[square],
+                &BISHOP_DIRECTIONS,
+                &mut attacks,
+            );
+        }
+        MagicTables {
+            rook,
+            bishop,
+            attacks,
+        }
+    }
+}
+
+/// Appends the slots for one square to `attacks`
+fn fill(square: usize, magic: u64, directions: &[(i8, i8)], attacks: &mut Vec<Bitboard>) -> Magic {
+    let mask = relevant_mask(square, directions);
+    let bits = mask.count_ones();
+    let magic = Magic {
+        mask,
+        magic,
+        shift: 64 - bits,
+        offset: attacks.len(),
+    };
+
+    // no square is attacked by nothing, so 0 marks a free slot
+    attacks.resize(attacks.len() + (1 << bits), 0);
+    for occupied in subsets(mask) {
+        let attack = sliding_attacks(square, occupied, directions);
+        let slot = &mut attacks[magic.index(occupied)];
+        assert!(
+            *slot == 0 || *slot == attack,
+            "bad magic for square {square}"
+        );
+        *slot = attack;
+    }
+    magic
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magic_attacks_match_rays() {
+        for square in 0..64 {
+            for (magic, directions) in [
+                (tables().rook[square], &ROOK_DIRECTIONS),
+                (tables().bishop[square], &BISHOP_DIRECTIONS),
+            ] {
+                for occupied in subsets(magic.mask) {
+                    // pieces outside the mask must not change anything
+                    let occupied = occupied | !magic.mask & 0x8100_0000_0000_0081;
+                    assert_eq!(
+                        tables().attacks[magic.index(occupied)],
+                        sliding_attacks(square, occupied, directions)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_relevant_mask() {
+        // a1 rook: a2-a7 and b1-g1
+        assert_eq!(relevant_mask(0, &ROOK_DIRECTIONS).count_ones(), 12);
+        // d4 bishop does not need the edge squares
+        assert_eq!(relevant_mask(27, &BISHOP_DIRECTIONS).count_ones(), 9);
+    }
+}
@@ -1,43 +1,103 @@
-use crate::{MoveOk, PieceType, PieceType::*, board::*};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-fn pos(r: i8, c: i8) -> Position {
-    Position { row: r, col: c }
-}
+use crate::bitboard::{
+    Bitboard, KING_ATTACKS, KNIGHT_ATTACKS, Squares, bishop_attacks, pawn_attacks, queen_attacks,
+    rook_attacks,
+};
+use crate::{Move, PieceType, PieceType::*, board::*, pieces::Color, rules::MoveType};
 
 const PROMOTION_PIECES: [PieceType; 4] = [Queen, Rook, Bishop, Knight];
 
-pub fn all_legal_moves(board: &Board) -> Vec<(Position, Position, Option<PieceType>)> {
-    let mut legal_moves: Vec<(Position, Position, Option<PieceType>)> = Vec::new();
-
-    for from_row in 0..BOARD_ROWS {
-        for from_col in 0..BOARD_COLS {
-            let from_pos = pos(from_row, from_col);
-
-            if let Some(piece) = board.get(from_pos)
-                && piece.color == board.move_turn
-            {
-                for to_row in 0..BOARD_ROWS {
-                    for to_col in 0..BOARD_COLS {
-                        let to_pos = pos(to_row, to_col);
-                        let mut tmp = board.clone();
-
-                        match tmp.move_piece(from_pos, to_pos, None) {
-                            Ok(MoveOk::Done) => {
-                                legal_moves.push((from_pos, to_pos, None));
-                            }
-                            Ok(MoveOk::NeedsPromotion) => {
-                                for &pp in &PROMOTION_PIECES {
-                                    let mut tmp2 = board.clone();
-                                    if let Ok(MoveOk::Done) =
-                                        tmp2.move_piece(from_pos, to_pos, Some(pp))
-                                    {
-                                        legal_moves.push((from_pos, to_pos, Some(pp)));
-                                    }
-                                }
-                            }
-                            Err(_e) => {}
-                        }
+/// Squares the piece on `from_pos` can go to if we forget about checks.
+/// Castling is only in there when castle_is_legal says so.
+fn pseudo_legal_targets(board: &Board, from_pos: Position, piece_type: PieceType) -> Bitboard {
+    let us = board.move_turn;
+    let own = board.occupancy[us.index()];
+    let them = board.occupancy[us.opposite().index()];
+    let occupied = own | them;
+    let square = from_pos.index();
+
+    match piece_type {
+        Pawn => {
+            let (forward, start_row) = match us {
+                Color::White => (1, 1),
+                Color::Black => (-1, 6),
+            };
+            let mut targets = 0;
+            let one = Position::new(from_pos.row + forward, from_pos.col);
+            if board.pos_on_board(one) && occupied & one.bit() == 0 {
+                targets |= one.bit();
+                let two = Position::new(one.row + forward, one.col);
+                if from_pos.row == start_row && occupied & two.bit() == 0 {
+                    targets |= two.bit();
+                }
+            }
+            let ep = board.en_passant.map_or(0, |ep| ep.bit());
+            targets | (pawn_attacks(us, square) & (them | ep))
+        }
+        Knight => KNIGHT_ATTACKS[square] & !own,
+        Bishop => bishop_attacks(square, occupied) & !own,
+        Rook => rook_attacks(square, occupied) & !own,
+        Queen => queen_attacks(square, occupied) & !own,
+        King => {
+            let mut targets = KING_ATTACKS[square] & !own;
+            for col in [from_pos.col + 2, from_pos.col - 2] {
+                let to_pos = Position::new(from_pos.row, col);
+                if board.pos_on_board(to_pos) && board.castle_is_legal(from_pos, to_pos).is_ok() {
+                    targets |= to_pos.bit();
+                }
+            }
+            targets
+        }
+    }
+}
+
+/// The move type of a pseudo legal move, same answer as classify_move_type
+fn move_type_of(
+    board: &Board,
+    piece_type: PieceType,
+    from_pos: Position,
+    to_pos: Position,
+) -> MoveType {
+    let is_capture = board.occupied() & to_pos.bit() != 0;
+    match piece_type {
+        Pawn if board.en_passant == Some(to_pos) && from_pos.col != to_pos.col => {
+            MoveType::EnPassant
+        }
+        Pawn if to_pos.row == 0 || to_pos.row == BOARD_ROWS - 1 => {
+            MoveType::PawnPromotion { is_capture }
+        }
+        King if (to_pos.col - from_pos.col).abs() == 2 => MoveType::Castle,
+        _ => MoveType::Normal { is_capture },
+    }
+}
+
+/// Generates the moves of every piece of the side to move, then drops the ones
+/// that leave the own king in check using the checks and pins of the position
+pub fn all_legal_moves(board: &Board) -> Vec<Move> {
+    let mut legal_moves: Vec<Move> = Vec::new();
+    let info = board.check_info();
+    // in double check only the king can move
+    let piece_types: &[PieceType] = if info.checkers.count_ones() > 1 {
+        &[King]
+    } else {
+        &PieceType::ALL
+    };
+
+    for &piece_type in piece_types {
+        for from_pos in Squares(board.pieces(board.move_turn, piece_type)) {
+            for to_pos in Squares(pseudo_legal_targets(board, from_pos, piece_type)) {
+                let move_type = move_type_of(board, piece_type, from_pos, to_pos);
+                if !board.is_king_safe_after(&info, from_pos, to_pos, move_type) {
+                    continue;
+                }
+
+                if let MoveType::PawnPromotion { .. } = move_type {
+                    for &pp in &PROMOTION_PIECES {
+                        legal_moves.push(board.build_move(from_pos, to_pos, move_type, Some(pp)));
                     }
+                } else {
+                    legal_moves.push(board.build_move(from_pos, to_pos, move_type, None));
                 }
             }
         }
@@ -45,7 +105,7 @@ pub fn all_legal_moves(board: &Board) -> Vec<(Position, Position, Option<PieceTy
     legal_moves
 }
 
-pub fn dfs(b: &Board, d: usize, depth_total: usize, totals: &mut [usize]) {
+pub fn dfs(b: &mut Board, d: usize, depth_total: usize, totals: &mut [usize]) {
     let moves = all_legal_moves(b);
     let idx = depth_total - d;
     totals[idx] += moves.len();
@@ -54,10 +114,10 @@ pub fn dfs(b: &Board, d: usize, depth_total: usize, totals: &mut [usize]) {
         return;
     }
 
-    for (from, to, prom_piece_type) in moves {
-        let mut next = b.clone();
-        next.move_piece(from, to, prom_piece_type).unwrap();
-        dfs(&next, d - 1, depth_total, totals);
+    for mv in moves {
+        b.play_move(mv).unwrap();
+        dfs(b, d - 1, depth_total, totals);
+        b.unmake_move();
     }
 }
 
@@ -67,3 +127,136 @@ pub fn perft_layers(board: &mut Board, depth: usize) -> Vec<usize> {
     dfs(board, depth, depth, &mut totals);
     totals
 }
+
+/// perft_layers spread over `threads` threads (at least one). The moves two
+/// plies deep are handed out one by one, so a thread that gets quick moves just
+/// takes more of them.
+pub fn perft_layers_parallel(board: &Board, depth: usize, threads: usize) -> Vec<usize> {
+    assert!(depth >= 1);
+    if depth < 3 {
+        return perft_layers(&mut board.clone_position(), depth);
+    }
+
+    let mut totals = vec![0usize; depth];
+    let mut jobs = Vec::new();
+    let mut root = board.clone_position();
+    for mv in all_legal_moves(&root) {
+        totals[0] += 1;
+        root.play_move(mv).unwrap();
+        for reply in all_legal_moves(&root) {
+            totals[1] += 1;
+            jobs.push((mv, reply));
+        }
+        root.unmake_move();
+    }
+
+    let next_job = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut board = board.clone_position();
+                    let mut layers = vec![0usize; depth - 2];
+                    while let Some(&(mv, reply)) =
+                        jobs.get(next_job.fetch_add(1, Ordering::Relaxed))
+                    {
+                        board.play_move(mv).unwrap();
+                        board.play_move(reply).unwrap();
+                        dfs(&mut board, depth - 2, depth - 2, &mut layers);
+                        board.unmake_move();
+                        board.unmake_move();
+                    }
+                    layers
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            let layers = worker.join().expect("perft worker panicked");
+            for (total, count) in totals[2..].iter_mut().zip(layers) {
+                *total += count;
+            }
+        }
+    });
+    totals
+}
+
+/// The leaf count for every root move, the same as `go perft` in stockfish.
+/// When a total is off, compare this with a working engine and follow the
+/// move with the wrong count down.
+pub fn perft_divide(board: &mut Board, depth: usize) -> Vec<(Move, usize)> {
+    assert!(depth >= 1);
+    let mut divide = Vec::new();
+    for mv in all_legal_moves(board) {
+        let nodes = if depth == 1 {
+            1
+        } else {
+            board.play_move(mv).unwrap();
+            let layers = perft_layers(board, depth - 1);
+            board.unmake_move();
+            layers[depth - 2]
+        };
+        divide.push((mv, nodes));
+    }
+    divide
+}
+
+/// What the moves of one depth were, the columns of the perft tables on the
+/// chessprogramming wiki
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PerftStats {
+    pub nodes: usize,
+    /// en passant included
+    pub captures: usize,
+    pub en_passants: usize,
+    pub castles: usize,
+    pub promotions: usize,
+    pub checks: usize,
+    /// a single check by a piece other than the one that moved, double checks
+    /// only count as double checks
+    pub discovered_checks: usize,
+    pub double_checks: usize,
+    pub checkmates: usize,
+}
+
+/// Where the moved pieces ended up, the king and the rook for a castle
+fn moved_to(mv: Move) -> Bitboard {
+    let rook_to = mv.castle_rook().map_or(0, |(_, rook_to)| rook_to.bit());
+    mv.to.bit() | rook_to
+}
+
+fn stats_dfs(board: &mut Board, depth_left: usize, stats: &mut [PerftStats]) {
+    let idx = stats.len() - depth_left;
+    for mv in all_legal_moves(board) {
+        let layer = &mut stats[idx];
+        layer.nodes += 1;
+        layer.captures += usize::from(mv.is_capture());
+        layer.en_passants += usize::from(mv.is_en_passant());
+        layer.castles += usize::from(mv.is_castle());
+        layer.promotions += usize::from(mv.is_promotion());
+
+        board.play_move(mv).unwrap();
+        let checkers = board.check_info().checkers;
+        if checkers != 0 {
+            layer.checks += 1;
+            if checkers.count_ones() > 1 {
+                layer.double_checks += 1;
+            } else if checkers & moved_to(mv) == 0 {
+                layer.discovered_checks += 1;
+            }
+            layer.checkmates += usize::from(all_legal_moves(board).is_empty());
+        }
+        if depth_left > 1 {
+            stats_dfs(board, depth_left - 1, stats);
+        }
+        board.unmake_move();
+    }
+}
+
+/// Like perft_layers, but counts the kinds of moves on every depth as well
+pub fn perft_stats(board: &mut Board, depth: usize) -> Vec<PerftStats> {
+    assert!(depth >= 1);
+    let mut stats = vec![PerftStats::default(); depth];
+    stats_dfs(board, depth, &mut stats);
+    stats
+}
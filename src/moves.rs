@@ -0,0 +1,150 @@
+use crate::board::{Board, Position};
+use crate::pieces::{Piece, PieceType};
+use crate::rules::MoveType;
+
+/// A legal move with everything a frontend needs to show it, like what was
+/// captured and if it was a castle or en passant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub from: Position,
+    pub to: Position,
+    pub promotion: Option<PieceType>,
+    /// the piece that moves, as it was before the move
+    pub piece: Piece,
+    pub captured: Option<Piece>,
+    pub move_type: MoveType,
+}
+
+impl Move {
+    pub fn is_capture(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    pub fn is_castle(&self) -> bool {
+        self.move_type == MoveType::Castle
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.move_type == MoveType::EnPassant
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        matches!(self.move_type, MoveType::PawnPromotion { .. })
+    }
+
+    /// where the captured piece stood, not the same as `to` for en passant
+    pub fn capture_square(&self) -> Option<Position> {
+        self.captured?;
+        if self.is_en_passant() {
+            Some(Position {
+                row: self.from.row,
+                col: self.to.col,
+            })
+        } else {
+            Some(self.to)
+        }
+    }
+
+    /// from and to position of the rook when castling
+    pub fn castle_rook(&self) -> Option<(Position, Position)> {
+        if !self.is_castle() {
+            return None;
+        }
+        let dc = (self.to.col - self.from.col).signum();
+        let rook_from = Position {
+            row: self.from.row,
+            col: if dc > 0 { 7 } else { 0 },
+        };
+        let rook_to = Position {
+            row: self.from.row,
+            col: self.from.col + dc,
+        };
+        Some((rook_from, rook_to))
+    }
+}
+
+impl Board {
+    /// Builds the Move for an already validated from and to position
+    pub fn build_move(
+        &self,
+        from_pos: Position,
+        to_pos: Position,
+        move_type: MoveType,
+        promotion: Option<PieceType>,
+    ) -> Move {
+        let piece = self.get(from_pos).expect("validated: from_pos has piece");
+        let captured = match move_type {
+            MoveType::EnPassant => self.get(Position {
+                row: from_pos.row,
+                col: to_pos.col,
+            }),
+            MoveType::Castle => None,
+            _ => self.get(to_pos),
+        };
+
+        Move {
+            from: from_pos,
+            to: to_pos,
+            promotion: match move_type {
+                MoveType::PawnPromotion { .. } => promotion,
+                _ => None,
+            },
+            piece,
+            captured,
+            move_type,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pieces::Color;
+
+    #[test]
+    fn test_legal_moves_flags() {
+        let board = Board::from_fen("r3k3/1P6/8/3pP3/8/8/8/4K2R w K d6 0 1").unwrap();
+        let moves = board.legal_moves();
+
+        let castle = moves.iter().find(|mv| mv.is_castle()).unwrap();
+        assert_eq!(
+            castle.castle_rook(),
+            Some((Position { row: 0, col: 7 }, Position { row: 0, col: 5 }))
+        );
+
+        let ep = moves.iter().find(|mv| mv.is_en_passant()).unwrap();
+        assert_eq!(ep.capture_square(), Some(Position { row: 4, col: 3 }));
+        assert_eq!(ep.captured.unwrap().color, Color::Black);
+
+        let promotions: Vec<&Move> = moves
+            .iter()
+            .filter(|mv| mv.is_promotion() && mv.is_capture())
+            .collect();
+        assert_eq!(promotions.len(), 4);
+        assert!(
+            promotions
+                .iter()
+                .all(|mv| mv.captured.unwrap().piece_type == PieceType::Rook)
+        );
+    }
+
+    #[test]
+    fn test_play_move() {
+        let mut board = Board::start_pos();
+        let mv = board
+            .legal_moves()
+            .into_iter()
+            .find(|mv| mv.piece.piece_type == PieceType::Knight)
+            .unwrap();
+
+        board.play_move(mv).unwrap();
+        assert_eq!(
+            board.get(mv.to),
+            Some(Piece {
+                has_moved: true,
+                ..mv.piece
+            })
+        );
+        assert_eq!(board.history.last().unwrap().mv, mv);
+    }
+}
@@ -0,0 +1,1041 @@
+use std::fmt;
+use std::io::{BufRead, ErrorKind};
+
+use crate::board::Board;
+use crate::fen::FenError;
+use crate::moves::Move;
+use crate::pieces::Color;
+use crate::rules::{GameResult, MoveError, MoveOk};
+use crate::san::SanError;
+
+/// The result token at the end of the movetext
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgnResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// "*", game still going or result unknown
+    Ongoing,
+}
+
+impl PgnResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PgnResult::WhiteWins => "1-0",
+            PgnResult::BlackWins => "0-1",
+            PgnResult::Draw => "1/2-1/2",
+            PgnResult::Ongoing => "*",
+        }
+    }
+
+    pub fn from_token(token: &str) -> Option<PgnResult> {
+        match token {
+            "1-0" => Some(PgnResult::WhiteWins),
+            "0-1" => Some(PgnResult::BlackWins),
+            "1/2-1/2" => Some(PgnResult::Draw),
+            "*" => Some(PgnResult::Ongoing),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PgnResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    /// a tag pair that is not `[Name "value"]`
+    InvalidTag(String),
+    UnterminatedComment,
+    /// a ")" without "(" or the other way around
+    UnbalancedVariation,
+    /// a NAG, comment or variation with no move before it to belong to
+    MisplacedToken(String),
+    UnexpectedCharacter(char),
+    /// the text ended before the result token
+    MissingResult,
+    InvalidFen(FenError),
+    /// ply counts half moves from the start of the game, 1 is the first move
+    IllegalMove {
+        ply: usize,
+        san: String,
+        error: SanError,
+    },
+    /// reading from a PgnReader failed, no more games are read after this
+    Io(ErrorKind),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag(tag) => write!(f, "invalid tag pair '{tag}'"),
+            PgnError::UnterminatedComment => write!(f, "comment without closing '}}'"),
+            PgnError::UnbalancedVariation => write!(f, "unbalanced variation parentheses"),
+            PgnError::MisplacedToken(token) => write!(f, "'{token}' before the first move"),
+            PgnError::UnexpectedCharacter(ch) => write!(f, "unexpected character '{ch}'"),
+            PgnError::MissingResult => write!(f, "movetext has no result token"),
+            PgnError::InvalidFen(e) => write!(f, "invalid FEN tag: {e}"),
+            PgnError::IllegalMove { ply, san, error } => {
+                write!(f, "move '{san}' at ply {ply}: {error}")
+            }
+            PgnError::Io(kind) => write!(f, "could not read PGN: {kind}"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<FenError> for PgnError {
+    fn from(e: FenError) -> Self {
+        PgnError::InvalidFen(e)
+    }
+}
+
+/// A move in the movetext with everything that was written after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnMove {
+    pub mv: Move,
+    /// the move as SAN, written the way to_san writes it
+    pub san: String,
+    /// numeric annotation glyphs, "!" and "?" style suffixes are stored as their NAG
+    pub nags: Vec<u8>,
+    pub comments: Vec<String>,
+    /// alternatives to this move, each played from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+/// One game of a PGN file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnGame {
+    /// tag pairs in the order they were written
+    pub tags: Vec<(String, String)>,
+    /// comments before the first move
+    pub comments: Vec<String>,
+    pub moves: Vec<PgnMove>,
+    pub result: PgnResult,
+    /// the starting position, from the FEN tag if there is one
+    pub start: Board,
+    /// the position after the last mainline move, its history holds the mainline
+    pub board: Board,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The moves of the mainline, without variations
+    pub fn mainline(&self) -> impl Iterator<Item = Move> + '_ {
+        self.moves.iter().map(|pgn_move| pgn_move.mv)
+    }
+
+    /// Parses one game and replays its moves, variations are checked as well.
+    pub fn from_pgn(pgn: &str) -> Result<PgnGame, PgnError> {
+        let mut cursor = Cursor::new(pgn);
+        let tags = cursor.tags()?;
+        let tokens = cursor.movetext()?;
+        cursor.skip_whitespace();
+        if let Some(ch) = cursor.peek() {
+            return Err(PgnError::UnexpectedCharacter(ch));
+        }
+        PgnGame::from_tokens(tags, tokens)
+    }
+
+    /// Reads only the tags, the result is taken from the Result tag
+    fn from_tags(pgn: &str) -> Result<PgnGame, PgnError> {
+        let tags = Cursor::new(pgn).tags()?;
+        let result = tags
+            .iter()
+            .find(|(tag, _)| tag == "Result")
+            .and_then(|(_, value)| PgnResult::from_token(value))
+            .unwrap_or(PgnResult::Ongoing);
+        PgnGame::from_tokens(tags, vec![Token::Result(result)])
+    }
+
+    fn from_tokens(tags: Vec<(String, String)>, tokens: Vec<Token>) -> Result<PgnGame, PgnError> {
+        let start = match tags.iter().find(|(tag, _)| tag == "FEN") {
+            Some((_, fen)) => Board::from_fen(fen)?,
+            None => Board::start_pos(),
+        };
+
+        let mut board = start.clone();
+        let mut tokens = tokens.into_iter().peekable();
+        let mut comments = Vec::new();
+        while let Some(Token::Comment(_)) = tokens.peek() {
+            if let Some(Token::Comment(comment)) = tokens.next() {
+                comments.push(comment);
+            }
+        }
+
+        let moves = parse_line(&mut tokens, &mut board, 1, false)?;
+        let result = match tokens.next() {
+            Some(Token::Result(result)) => result,
+            _ => return Err(PgnError::MissingResult),
+        };
+
+        Ok(PgnGame {
+            tags,
+            comments,
+            moves,
+            result,
+            start,
+            board,
+        })
+    }
+}
+
+/// The tags every PGN game has, written first and in this order
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// Export lines are never longer than this
+const LINE_WIDTH: usize = 80;
+
+impl From<GameResult> for PgnResult {
+    fn from(result: GameResult) -> Self {
+        match result {
+            GameResult::Checkmate(Color::White)
+            | GameResult::Resignation(Color::White)
+            | GameResult::Timeout(Color::White) => PgnResult::WhiteWins,
+            GameResult::Checkmate(Color::Black)
+            | GameResult::Resignation(Color::Black)
+            | GameResult::Timeout(Color::Black) => PgnResult::BlackWins,
+            GameResult::Stalemate
+            | GameResult::FiftyMoveRule
+            | GameResult::SeventyFiveMoveRule
+            | GameResult::ThreefoldRepetition
+            | GameResult::FivefoldRepetition
+            | GameResult::InsufficientMaterial
+            | GameResult::DrawAgreed
+            | GameResult::DrawOnTime => PgnResult::Draw,
+        }
+    }
+}
+
+impl PgnGame {
+    /// An empty record starting from `start`, moves are added with push
+    pub fn new(start: Board) -> PgnGame {
+        let mut start = start;
+        start.history.clear();
+        let result = finished_result(&start);
+        PgnGame {
+            tags: Vec::new(),
+            comments: Vec::new(),
+            moves: Vec::new(),
+            result,
+            board: start.clone(),
+            start,
+        }
+    }
+
+    /// A record of every move in the board's history
+    pub fn from_board(board: &Board) -> PgnGame {
+        let mut start = board.clone();
+        while start.unmake_move().is_some() {}
+
+        let mut game = PgnGame::new(start);
+        for undo in &board.history {
+            game.push(undo.mv)
+                .expect("validated: history moves are legal");
+        }
+        game
+    }
+
+    /// Sets a tag, replacing the value if the tag is already there
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Plays a move and adds it to the mainline. The result is updated from
+    /// game_over, nothing is recorded when a promotion piece is missing.
+    pub fn push(&mut self, mv: Move) -> Result<MoveOk, MoveError> {
+        let before = self.board.clone_position();
+        if self.board.play_move(mv)? == MoveOk::NeedsPromotion {
+            return Ok(MoveOk::NeedsPromotion);
+        }
+
+        let played = self
+            .board
+            .history
+            .last()
+            .expect("validated: move was played")
+            .mv;
+        self.moves.push(PgnMove {
+            mv: played,
+            san: before.to_san(played),
+            nags: Vec::new(),
+            comments: Vec::new(),
+            variations: Vec::new(),
+        });
+        self.result = finished_result(&self.board);
+        Ok(MoveOk::Done)
+    }
+
+    /// Writes the game in PGN export format
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.as_str(),
+                "Date" => self.tag(name).unwrap_or("????.??.??"),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            write_tag(&mut pgn, name, value);
+        }
+
+        let start_fen = self.start.to_fen();
+        let custom_start = start_fen != Board::start_pos().to_fen();
+        if custom_start {
+            write_tag(&mut pgn, "SetUp", "1");
+            write_tag(&mut pgn, "FEN", &start_fen);
+        }
+        for (name, value) in &self.tags {
+            let written = SEVEN_TAG_ROSTER.contains(&name.as_str())
+                || (custom_start && (name == "SetUp" || name == "FEN"));
+            if !written {
+                write_tag(&mut pgn, name, value);
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        for comment in &self.comments {
+            push_comment(&mut tokens, comment);
+        }
+        self.movetext_tokens(&mut tokens, &self.moves, 0);
+        tokens.push(self.result.as_str().to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+
+    /// Turns a line of moves into tokens, `first_ply` counts from the start position
+    fn movetext_tokens(&self, tokens: &mut Vec<String>, moves: &[PgnMove], first_ply: usize) {
+        // black moves need their number after a comment or variation
+        let mut needs_number = true;
+        for (i, pgn_move) in moves.iter().enumerate() {
+            let ply = first_ply + i + usize::from(self.start.move_turn == Color::Black);
+            let number = self.start.fullmove_number as usize + ply / 2;
+            if ply.is_multiple_of(2) {
+                tokens.push(format!("{number}."));
+            } else if needs_number {
+                tokens.push(format!("{number}..."));
+            }
+            tokens.push(pgn_move.san.clone());
+            needs_number = false;
+
+            for nag in &pgn_move.nags {
+                tokens.push(format!("${nag}"));
+            }
+            for comment in &pgn_move.comments {
+                push_comment(tokens, comment);
+                needs_number = true;
+            }
+            for variation in &pgn_move.variations {
+                let start = tokens.len();
+                self.movetext_tokens(tokens, variation, first_ply + i);
+                if tokens.len() == start {
+                    tokens.push("()".to_string());
+                } else {
+                    tokens[start].insert(0, '(');
+                    tokens.last_mut().expect("validated: not empty").push(')');
+                }
+                needs_number = true;
+            }
+        }
+    }
+}
+
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_pgn())
+    }
+}
+
+/// The result of game_over, a draw that still has to be claimed does not end the game
+fn finished_result(board: &Board) -> PgnResult {
+    board
+        .game_over()
+        .filter(|result| !result.is_claimable())
+        .map_or(PgnResult::Ongoing, PgnResult::from)
+}
+
+fn write_tag(pgn: &mut String, name: &str, value: &str) {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{name} \"{escaped}\"]\n"));
+}
+
+/// Comments are split into words so long ones can be wrapped
+fn push_comment(tokens: &mut Vec<String>, comment: &str) {
+    let words: Vec<&str> = comment.split_whitespace().collect();
+    match words.as_slice() {
+        [] => tokens.push("{}".to_string()),
+        [word] => tokens.push(format!("{{{word}}}")),
+        [first, middle @ .., last] => {
+            tokens.push(format!("{{{first}"));
+            tokens.extend(middle.iter().map(|word| word.to_string()));
+            tokens.push(format!("{last}}}"));
+        }
+    }
+}
+
+/// Reads games one at a time from a PGN file, so a whole dump never has to be
+/// in memory. A game that fails to parse is returned as an error and reading
+/// goes on from the next `[Event` tag.
+pub struct PgnReader<R> {
+    reader: R,
+    skip_movetext: bool,
+    /// a line that was read but belongs to the next game
+    next_line: Option<String>,
+    resync: bool,
+    done: bool,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            skip_movetext: false,
+            next_line: None,
+            resync: false,
+            done: false,
+        }
+    }
+
+    /// Only reads the tags. The games have no moves, the result comes from the
+    /// Result tag and the board is the starting position.
+    pub fn skip_movetext(mut self, skip: bool) -> Self {
+        self.skip_movetext = skip;
+        self
+    }
+
+    fn read_line(&mut self) -> Result<Option<String>, PgnError> {
+        if let Some(line) = self.next_line.take() {
+            return Ok(Some(line));
+        }
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line)),
+            Err(e) => Err(PgnError::Io(e.kind())),
+        }
+    }
+
+    /// Collects the text of the next game, None at the end of the file
+    fn next_game_text(&mut self) -> Result<Option<String>, PgnError> {
+        let mut text = String::new();
+        let mut in_movetext = false;
+        // inside a "{" comment a "[" at the start of a line is not a tag. They do
+        // not nest, the first "}" ends it.
+        let mut in_comment = false;
+        let mut seen_event = false;
+
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim_start();
+            if self.resync {
+                if !trimmed.starts_with("[Event") {
+                    continue;
+                }
+                self.resync = false;
+            }
+            let new_tags = trimmed.starts_with('[') && in_movetext && !in_comment;
+            if new_tags || (trimmed.starts_with("[Event") && seen_event) {
+                self.next_line = Some(line);
+                break;
+            }
+            seen_event |= trimmed.starts_with("[Event");
+            if !trimmed.is_empty() && !trimmed.starts_with('[') && !trimmed.starts_with('%') {
+                in_movetext = true;
+            }
+
+            if in_movetext {
+                for ch in line.chars() {
+                    match ch {
+                        '{' => in_comment = true,
+                        '}' => in_comment = false,
+                        ';' if !in_comment => break,
+                        _ => {}
+                    }
+                }
+                if self.skip_movetext {
+                    continue;
+                }
+            }
+            text.push_str(&line);
+        }
+
+        if text.trim().is_empty() && !in_movetext {
+            return Ok(None);
+        }
+        Ok(Some(text))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let text = match self.next_game_text() {
+            Ok(Some(text)) => text,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+
+        let game = if self.skip_movetext {
+            PgnGame::from_tags(&text)
+        } else {
+            PgnGame::from_pgn(&text)
+        };
+        if game.is_err() {
+            self.resync = true;
+        }
+        Some(game)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    San(String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    Result(PgnResult),
+}
+
+/// Reads moves until the result token or the ")" that closes a variation.
+/// `first_ply` is the ply of the first move in this line.
+fn parse_line(
+    tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
+    board: &mut Board,
+    first_ply: usize,
+    in_variation: bool,
+) -> Result<Vec<PgnMove>, PgnError> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+    // the board before the last move, variations start from there
+    let mut before_last: Option<Board> = None;
+    let mut leading_comments = Vec::new();
+
+    loop {
+        match tokens.peek() {
+            None => {
+                return if in_variation {
+                    Err(PgnError::UnbalancedVariation)
+                } else {
+                    Ok(moves)
+                };
+            }
+            Some(Token::Result(_)) => {
+                return if in_variation {
+                    Err(PgnError::UnbalancedVariation)
+                } else {
+                    Ok(moves)
+                };
+            }
+            Some(Token::Close) => {
+                return if in_variation {
+                    tokens.next();
+                    Ok(moves)
+                } else {
+                    Err(PgnError::UnbalancedVariation)
+                };
+            }
+            _ => {}
+        }
+
+        let ply = first_ply + moves.len();
+        match tokens.next().expect("validated: peeked token") {
+            Token::San(san) => {
+                let mv = board
+                    .parse_san(&san)
+                    .map_err(|error| PgnError::IllegalMove {
+                        ply,
+                        san: san.clone(),
+                        error,
+                    })?;
+                let canonical = board.to_san(mv);
+                before_last = Some(board.clone_position());
+                board
+                    .play_move(mv)
+                    .expect("validated: parsed move is legal");
+                moves.push(PgnMove {
+                    mv,
+                    san: canonical,
+                    nags: Vec::new(),
+                    comments: std::mem::take(&mut leading_comments),
+                    variations: Vec::new(),
+                });
+            }
+            Token::Comment(comment) => match moves.last_mut() {
+                Some(last) => last.comments.push(comment),
+                None => leading_comments.push(comment),
+            },
+            Token::Nag(nag) => match moves.last_mut() {
+                Some(last) => last.nags.push(nag),
+                None => return Err(PgnError::MisplacedToken(format!("${nag}"))),
+            },
+            Token::Open => {
+                let (Some(last), Some(before)) = (moves.last_mut(), before_last.as_ref()) else {
+                    return Err(PgnError::MisplacedToken("(".to_string()));
+                };
+                let mut variation_board = before.clone();
+                let variation = parse_line(tokens, &mut variation_board, ply - 1, true)?;
+                last.variations.push(variation);
+            }
+            Token::Close | Token::Result(_) => unreachable!("handled above"),
+        }
+    }
+}
+
+/// Walks over the PGN text one character at a time
+struct Cursor<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.chars.next();
+        }
+    }
+
+    fn skip_line(&mut self) -> String {
+        let mut line = String::new();
+        for ch in self.chars.by_ref() {
+            if ch == '\n' {
+                break;
+            }
+            line.push(ch);
+        }
+        line
+    }
+
+    /// Reads the tag pair section, stops at the first character that is not a tag
+    fn tags(&mut self) -> Result<Vec<(String, String)>, PgnError> {
+        let mut tags = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('[') => tags.push(self.tag()?),
+                // escape lines, meant for other programs
+                Some('%') => {
+                    self.skip_line();
+                }
+                _ => return Ok(tags),
+            }
+        }
+    }
+
+    fn tag(&mut self) -> Result<(String, String), PgnError> {
+        self.chars.next();
+        let mut raw = String::new();
+        let mut name = String::new();
+        let mut value = String::new();
+        let mut in_value = false;
+        let mut closed_value = false;
+
+        while let Some(ch) = self.chars.next() {
+            raw.push(ch);
+            if in_value {
+                match ch {
+                    '\\' => {
+                        if let Some(escaped) = self.chars.next() {
+                            raw.push(escaped);
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        in_value = false;
+                        closed_value = true;
+                    }
+                    _ => value.push(ch),
+                }
+                continue;
+            }
+            match ch {
+                ']' if closed_value && !name.is_empty() => return Ok((name, value)),
+                '"' if !closed_value && !name.is_empty() => in_value = true,
+                '\n' => break,
+                ch if ch.is_whitespace() => {}
+                ch if !closed_value && (ch.is_alphanumeric() || ch == '_') => name.push(ch),
+                _ => break,
+            }
+        }
+        Err(PgnError::InvalidTag(format!("[{raw}")))
+    }
+
+    /// Splits the movetext into tokens, up to and including the result token
+    fn movetext(&mut self) -> Result<Vec<Token>, PgnError> {
+        let mut tokens = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let Some(ch) = self.peek() else {
+                return Ok(tokens);
+            };
+            match ch {
+                '{' => {
+                    self.chars.next();
+                    let mut comment = String::new();
+                    loop {
+                        match self.chars.next() {
+                            Some('}') => break,
+                            Some(ch) => comment.push(ch),
+                            None => return Err(PgnError::UnterminatedComment),
+                        }
+                    }
+                    tokens.push(Token::Comment(comment.trim().to_string()));
+                }
+                ';' => {
+                    self.chars.next();
+                    let comment = self.skip_line();
+                    tokens.push(Token::Comment(comment.trim().to_string()));
+                }
+                '(' => {
+                    self.chars.next();
+                    tokens.push(Token::Open);
+                }
+                ')' => {
+                    self.chars.next();
+                    tokens.push(Token::Close);
+                }
+                '$' => {
+                    self.chars.next();
+                    let digits = self.take_while(|ch| ch.is_ascii_digit());
+                    let nag = digits
+                        .parse()
+                        .map_err(|_| PgnError::UnexpectedCharacter('$'))?;
+                    tokens.push(Token::Nag(nag));
+                }
+                '!' | '?' => {
+                    let suffix = self.take_while(|ch| ch == '!' || ch == '?');
+                    let nag = match suffix.as_str() {
+                        "!" => 1,
+                        "?" => 2,
+                        "!!" => 3,
+                        "??" => 4,
+                        "!?" => 5,
+                        "?!" => 6,
+                        _ => return Err(PgnError::UnexpectedCharacter(ch)),
+                    };
+                    tokens.push(Token::Nag(nag));
+                }
+                '*' => {
+                    self.chars.next();
+                    tokens.push(Token::Result(PgnResult::Ongoing));
+                    return Ok(tokens);
+                }
+                ch if is_symbol_char(ch) => {
+                    let symbol = self.take_while(is_symbol_char);
+                    if let Some(result) = PgnResult::from_token(&symbol) {
+                        tokens.push(Token::Result(result));
+                        return Ok(tokens);
+                    }
+                    if symbol.chars().all(|ch| ch.is_ascii_digit()) {
+                        // move number, "12." or "12..."
+                        self.take_while(|ch| ch == '.');
+                        continue;
+                    }
+                    tokens.push(Token::San(symbol));
+                }
+                '.' => {
+                    self.take_while(|ch| ch == '.');
+                }
+                ch => return Err(PgnError::UnexpectedCharacter(ch)),
+            }
+        }
+    }
+
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(ch) = self.peek() {
+            if !keep(ch) {
+                break;
+            }
+            taken.push(ch);
+            self.chars.next();
+        }
+        taken
+    }
+}
+
+fn is_symbol_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || matches!(ch, '_' | '+' | '#' | '=' | ':' | '-' | '/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Position;
+    use crate::pieces::PieceType;
+
+    const IMMORTAL: &str = r#"[Event "London"]
+[Site "London ENG"]
+[Date "1851.06.21"]
+[Round "?"]
+[White "Adolf Anderssen"]
+[Black "Lionel Kieseritzky"]
+[Result "1-0"]
+[Annotator "someone \"quoted\""]
+
+{The Immortal Game} 1. e4 e5 2. f4 exf4 3. Bc4 Qh4+ 4. Kf1 b5 5. Bxb5 Nf6 6. Nf3
+Qh6 7. d3 Nh5 8. Nh4 Qg5 9. Nf5 c6 10. g4 Nf6 11. Rg1 cxb5 12. h4 Qg6 13. h5
+Qg5 14. Qf3 Ng8 15. Bxf4 Qf6 16. Nc3 Bc5 17. Nd5 Qxb2 18. Bd6 Bxg1 19. e5 Qxa1+
+20. Ke2 Na6 21. Nxg7+ Kd8 22. Qf6+ Nxf6 23. Be7# 1-0
+"#;
+
+    #[test]
+    fn test_parse_full_game() {
+        let game = PgnGame::from_pgn(IMMORTAL).unwrap();
+
+        assert_eq!(game.tag("White"), Some("Adolf Anderssen"));
+        assert_eq!(game.tag("Annotator"), Some("someone \"quoted\""));
+        assert_eq!(game.tags.len(), 8);
+        assert_eq!(game.comments, vec!["The Immortal Game".to_string()]);
+        assert_eq!(game.moves.len(), 45);
+        assert_eq!(game.result, PgnResult::WhiteWins);
+        assert_eq!(game.moves.last().unwrap().san, "Be7#");
+        assert!(game.board.is_check_mate());
+        assert_eq!(game.board.history.len(), 45);
+    }
+
+    #[test]
+    fn test_parse_comments_nags_and_variations() {
+        let pgn = "1. e4 $1 {best by test} e5 (1... c5 2. Nf3 (2. c3) d6) (1... e6) \
+                   2. Nf3!? ; a line comment\n Nc6 *";
+        let game = PgnGame::from_pgn(pgn).unwrap();
+
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(game.result, PgnResult::Ongoing);
+        assert_eq!(game.moves[0].nags, vec![1]);
+        assert_eq!(game.moves[0].comments, vec!["best by test".to_string()]);
+        assert_eq!(game.moves[2].nags, vec![5]);
+        assert_eq!(game.moves[2].comments, vec!["a line comment".to_string()]);
+
+        let variations = &game.moves[1].variations;
+        assert_eq!(variations.len(), 2);
+        assert_eq!(variations[0][0].san, "c5");
+        assert_eq!(variations[0][1].variations[0][0].san, "c3");
+        assert_eq!(variations[1][0].mv.to, Position::E6);
+    }
+
+    #[test]
+    fn test_parse_fen_tag() {
+        let pgn = r#"[SetUp "1"]
+[FEN "4k3/P7/8/8/8/8/8/4K3 w - - 0 1"]
+
+1. a8=Q+ Kd7 1/2-1/2"#;
+        let game = PgnGame::from_pgn(pgn).unwrap();
+        assert_eq!(game.moves[0].mv.promotion, Some(PieceType::Queen));
+        assert_eq!(game.start.to_fen(), "4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(game.result, PgnResult::Draw);
+    }
+
+    #[test]
+    fn test_first_illegal_move() {
+        let pgn = "1. e4 e5 2. Nf3 Nc6 3. Bb5 Ke7 4. Ke3 *";
+        assert_eq!(
+            PgnGame::from_pgn(pgn),
+            Err(PgnError::IllegalMove {
+                ply: 7,
+                san: "Ke3".to_string(),
+                error: SanError::Illegal("Ke3".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            PgnGame::from_pgn("[Event \"x\"\n1. e4 *"),
+            Err(PgnError::InvalidTag("[Event \"x\"\n".to_string()))
+        );
+        assert_eq!(
+            PgnGame::from_pgn("1. e4 {never closed"),
+            Err(PgnError::UnterminatedComment)
+        );
+        assert_eq!(
+            PgnGame::from_pgn("1. e4 (1. d4 *"),
+            Err(PgnError::UnbalancedVariation)
+        );
+        assert_eq!(
+            PgnGame::from_pgn("1. e4 e5) *"),
+            Err(PgnError::UnbalancedVariation)
+        );
+        assert_eq!(PgnGame::from_pgn("1. e4 e5"), Err(PgnError::MissingResult));
+        assert!(matches!(
+            PgnGame::from_pgn("[FEN \"8/8/8/8/8/8/8/8\"]\n*"),
+            Err(PgnError::InvalidFen(_))
+        ));
+    }
+
+    #[test]
+    fn test_export_played_game() {
+        let mut board = Board::start_pos();
+        for san in ["f3", "e5", "g4", "Qh4#"] {
+            board.play_san(san).unwrap();
+        }
+        let mut game = PgnGame::from_board(&board);
+        game.set_tag("White", "Fool");
+        game.set_tag("Annotator", "a \"quote\"");
+
+        assert_eq!(game.result, PgnResult::BlackWins);
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"Fool\"]\n[Black \"?\"]\n[Result \"0-1\"]\n[Annotator \"a \\\"quote\\\"\"]\n\
+             \n1. f3 e5 2. g4 Qh4# 0-1\n"
+        );
+    }
+
+    #[test]
+    fn test_export_custom_start() {
+        let fen = "4k3/8/8/8/8/8/1p6/4K3 b - - 0 40";
+        let mut game = PgnGame::new(Board::from_fen(fen).unwrap());
+        let mv = game.board.parse_san("b1=Q+").unwrap();
+        game.push(mv).unwrap();
+        let mv = game.board.parse_san("Kd2").unwrap();
+        game.push(mv).unwrap();
+
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/1p6/4K3 b - - 0 40\"]\n"));
+        assert!(pgn.ends_with("\n40... b1=Q+ 41. Kd2 *\n"));
+
+        let imported = PgnGame::from_pgn(&pgn).unwrap();
+        assert_eq!(imported.board, game.board);
+    }
+
+    #[test]
+    fn test_export_wraps_and_round_trips() {
+        let game = PgnGame::from_pgn(IMMORTAL).unwrap();
+        let pgn = game.to_pgn();
+        assert!(pgn.lines().all(|line| line.len() <= 80));
+        assert!(pgn.contains("\n\n{The Immortal Game} 1. e4 e5 2. f4 exf4 3. Bc4 Qh4+ 4. Kf1"));
+
+        let annotated =
+            PgnGame::from_pgn("1. e4 $1 {a long comment} e5 (1... c5 2. Nf3) 2. Nf3 *").unwrap();
+        let pgn = annotated.to_pgn();
+        assert!(pgn.ends_with("1. e4 $1 {a long comment} 1... e5 (1... c5 2. Nf3) 2. Nf3 *\n"));
+
+        for game in [game, annotated] {
+            let again = PgnGame::from_pgn(&game.to_pgn()).unwrap();
+            assert_eq!(again.moves, game.moves);
+            assert_eq!(again.comments, game.comments);
+            assert_eq!(again.result, game.result);
+        }
+    }
+
+    const GAMES: &str = r#"[Event "first"]
+[Result "1-0"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+
+[Event "broken"]
+[Result "*"]
+
+1. e4 e5 2. Ke3 {the king walks
+[into] nothing} *
+[Round "2"]
+
+1. d4 *
+
+[Event "third"]
+[Result "1/2-1/2"]
+[FEN "4k3/8/8/8/8/8/8/4K3 w - - 0 1"]
+
+{no moves at all} 1/2-1/2
+"#;
+
+    #[test]
+    fn test_reader_yields_games_and_resyncs() {
+        let games: Vec<Result<PgnGame, PgnError>> = PgnReader::new(GAMES.as_bytes()).collect();
+        assert_eq!(games.len(), 3);
+
+        let first = games[0].as_ref().unwrap();
+        assert_eq!(first.tag("Event"), Some("first"));
+        assert_eq!(first.moves.len(), 7);
+
+        assert!(matches!(
+            games[1],
+            Err(PgnError::IllegalMove { ply: 3, .. })
+        ));
+
+        let third = games[2].as_ref().unwrap();
+        assert_eq!(third.tag("Event"), Some("third"));
+        assert_eq!(third.comments, vec!["no moves at all".to_string()]);
+        assert_eq!(third.result, PgnResult::Draw);
+    }
+
+    #[test]
+    fn test_reader_braces_do_not_nest() {
+        let pgn = "[Event \"a\"]\n\n1. e4 {see {this} e5 1-0\n\n[Site \"b\"]\n\n1. d4 0-1\n";
+        let games: Vec<PgnGame> = PgnReader::new(pgn.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].moves.len(), 2);
+        assert_eq!(games[0].moves[0].comments, vec!["see {this".to_string()]);
+        assert_eq!(games[1].tag("Site"), Some("b"));
+        assert_eq!(games[1].result, PgnResult::BlackWins);
+    }
+
+    #[test]
+    fn test_reader_tags_only() {
+        let games: Vec<PgnGame> = PgnReader::new(GAMES.as_bytes())
+            .skip_movetext(true)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let events: Vec<Option<&str>> = games.iter().map(|game| game.tag("Event")).collect();
+        // without the movetext the broken game is not noticed, so its second half
+        // shows up as a game of its own
+        assert_eq!(
+            events,
+            vec![Some("first"), Some("broken"), None, Some("third")]
+        );
+        assert!(games.iter().all(|game| game.moves.is_empty()));
+        assert_eq!(games[0].result, PgnResult::WhiteWins);
+        assert_eq!(games[3].start.to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+}
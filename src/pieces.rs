@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PieceType {
     Pawn,
     Bishop,
@@ -8,13 +8,68 @@ pub enum PieceType {
     King,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+impl PieceType {
+    pub const ALL: [PieceType; 6] = [
+        PieceType::Pawn,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Knight,
+        PieceType::Queen,
+        PieceType::King,
+    ];
+
+    /// Position in ALL, used to index bitboards
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The uppercase letter used in FEN and SAN, P for pawn
+    pub fn letter(self) -> char {
+        match self {
+            PieceType::Pawn => 'P',
+            PieceType::Bishop => 'B',
+            PieceType::Rook => 'R',
+            PieceType::Knight => 'N',
+            PieceType::Queen => 'Q',
+            PieceType::King => 'K',
+        }
+    }
+
+    /// Reverse of letter, ignores case
+    pub fn from_letter(ch: char) -> Option<PieceType> {
+        match ch.to_ascii_uppercase() {
+            'P' => Some(PieceType::Pawn),
+            'B' => Some(PieceType::Bishop),
+            'R' => Some(PieceType::Rook),
+            'N' => Some(PieceType::Knight),
+            'Q' => Some(PieceType::Queen),
+            'K' => Some(PieceType::King),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Color {
     White,
     Black,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Color {
+    /// 0 for white and 1 for black, used to index bitboards
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Piece {
     pub piece_type: PieceType,
     pub color: Color,
@@ -1,9 +1,18 @@
-use crate::board::{BOARD_COLS, BOARD_ROWS, Board, Position};
+use crate::bitboard::{
+    Bitboard, KING_ATTACKS, KNIGHT_ATTACKS, Squares, between, bishop_attacks, line, pawn_attacks,
+    rook_attacks,
+};
+use crate::board::{BOARD_COLS, BOARD_ROWS, Board, CastleSide, CastlingRights, Position};
 use crate::movegen::all_legal_moves;
-use crate::pieces::{Color, PieceType};
+use crate::moves::Move;
+use crate::pieces::{Color, Piece, PieceType};
+use crate::zobrist;
 
 // ASCII board
 
+/// a1, c1, ... the squares where (row + col) is even
+const DARK_SQUARES: Bitboard = 0xAA55_AA55_AA55_AA55;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MoveError {
     EmptyFrom,
@@ -31,12 +40,75 @@ pub enum MoveOk {
     NeedsPromotion,
 }
 
+/// Checks and pins of the side to move, from Board::check_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckInfo {
+    /// square index of the king
+    pub king: usize,
+    /// enemy pieces giving check
+    pub checkers: Bitboard,
+    /// own pieces that can only move along the line to their king
+    pub pinned: Bitboard,
+    /// squares a move other than a king move has to end on, every square when
+    /// not in check and none in double check
+    pub check_mask: Bitboard,
+}
+
+/// What set_values needs to remember so unmake_move can put the board back exactly
+/// like it was
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Undo {
+    /// the move that was played, its piece still has the old has_moved
+    pub mv: Move,
+    /// the rook before castling
+    pub castle_rook: Option<Piece>,
+    pub en_passant: Option<Position>,
+    pub castling: CastlingRights,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    /// the key of the position before the move, used for repetitions
+    pub zobrist: u64,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum GameResult {
     Checkmate(Color),
     Stalemate,
+    /// 50 moves by each side without a pawn move or capture, a draw only if
+    /// a player claims it
+    FiftyMoveRule,
+    /// 75 moves by each side without a pawn move or capture, always a draw
+    SeventyFiveMoveRule,
+    /// the same position for the third time, a draw if a player claims it
+    ThreefoldRepetition,
+    /// the same position for the fifth time, always a draw
+    FivefoldRepetition,
+    /// nobody has enough pieces left to mate
+    InsufficientMaterial,
+    /// the other player resigned, holds the winner like Checkmate
+    Resignation(Color),
+    /// a draw offer was accepted
+    DrawAgreed,
+    /// the other player ran out of time, holds the winner
+    Timeout(Color),
+    /// a player ran out of time but the other one could never mate
+    DrawOnTime,
 }
 
+impl GameResult {
+    /// true for the draws a player has to claim, the game can go on without a claim
+    pub fn is_claimable(&self) -> bool {
+        matches!(
+            self,
+            GameResult::FiftyMoveRule | GameResult::ThreefoldRepetition
+        )
+    }
+}
+
+/// halfmove clock values for the fifty and seventy-five move rules
+pub const FIFTY_MOVE_PLIES: u32 = 100;
+pub const SEVENTY_FIVE_MOVE_PLIES: u32 = 150;
+
 impl Board {
     pub fn move_piece(
         &mut self,
@@ -44,6 +116,38 @@ impl Board {
         to_pos: Position,
         prom_piece_type: Option<PieceType>,
     ) -> Result<MoveOk, MoveError> {
+        let move_type = self.check_move(from_pos, to_pos)?;
+
+        if let MoveType::PawnPromotion { .. } = move_type
+            && prom_piece_type.is_none()
+        {
+            return Ok(MoveOk::NeedsPromotion);
+        }
+
+        self.set_values(from_pos, to_pos, move_type, prom_piece_type);
+
+        Ok(MoveOk::Done)
+    }
+
+    /// Plays a move from legal_moves
+    pub fn play_move(&mut self, mv: Move) -> Result<MoveOk, MoveError> {
+        self.move_piece(mv.from, mv.to, mv.promotion)
+    }
+
+    /// Runs every legality check of move_piece without moving anything,
+    /// returns the type of the move if it is legal
+    pub fn check_move(&self, from_pos: Position, to_pos: Position) -> Result<MoveType, MoveError> {
+        self.check_move_with(&self.check_info(), from_pos, to_pos)
+    }
+
+    /// Same as check_move but reuses a check_info, so callers that check
+    /// several moves in one position only find checkers and pins once
+    pub fn check_move_with(
+        &self,
+        info: &CheckInfo,
+        from_pos: Position,
+        to_pos: Position,
+    ) -> Result<MoveType, MoveError> {
         self.basic_precheck(from_pos, to_pos)?;
         let move_type: MoveType = self.classify_move_type(from_pos, to_pos);
 
@@ -56,17 +160,11 @@ impl Board {
             }
         }?;
 
-        self.move_in_check(from_pos, to_pos, move_type)?;
-
-        if let MoveType::PawnPromotion { .. } = move_type
-            && prom_piece_type.is_none()
-        {
-            return Ok(MoveOk::NeedsPromotion);
+        if !self.is_king_safe_after(info, from_pos, to_pos, move_type) {
+            return Err(MoveError::SelfCheck);
         }
 
-        self.set_values(from_pos, to_pos, move_type, prom_piece_type);
-
-        Ok(MoveOk::Done)
+        Ok(move_type)
     }
 
     pub fn basic_precheck(&self, from_pos: Position, to_pos: Position) -> Result<(), MoveError> {
@@ -210,9 +308,18 @@ impl Board {
 
         let (dr, dc) = from_pos.delta(to_pos);
 
-        if from_piece.has_moved {
+        // the king moving takes away both rights, a rook moving or getting captured only one
+        if !self.castling.has_any(from_piece.color) {
             return Err(MoveError::KingHasMoved);
         }
+        let side = if dc > 0 {
+            CastleSide::KingSide
+        } else {
+            CastleSide::QueenSide
+        };
+        if !self.castling.has(from_piece.color, side) {
+            return Err(MoveError::RookHasMoved);
+        }
 
         let expected_king_row = match from_piece.color {
             Color::White => 0,
@@ -229,10 +336,7 @@ impl Board {
         let Some(rook_piece) = self.get(rook_pos) else {
             return Err(MoveError::RookHasMoved);
         };
-        if rook_piece.piece_type != PieceType::Rook {
-            return Err(MoveError::RookHasMoved);
-        }
-        if rook_piece.has_moved {
+        if rook_piece.piece_type != PieceType::Rook || rook_piece.color != from_piece.color {
             return Err(MoveError::RookHasMoved);
         }
 
@@ -293,7 +397,8 @@ impl Board {
         Ok(())
     }
 
-    /// checks if path is blocked or not
+    /// checks if path is blocked or not, gives the first piece in the way.
+    /// Without to_pos the path goes on to the edge of the board.
     pub fn check_clear_path(
         &self,
         from_pos: Position,
@@ -301,152 +406,158 @@ impl Board {
         row_offset: i8,
         col_offset: i8,
     ) -> Option<Position> {
-        let mut path_row = row_offset + from_pos.row;
-        let mut path_col = col_offset + from_pos.col;
-
-        loop {
-            let pos = Position {
-                row: path_row,
-                col: path_col,
-            };
-
-            if !self.pos_on_board(pos) {
-                break;
-            }
-
-            if let Some(target) = to_pos
-                && pos == target
-            {
-                return None;
-            }
-
-            if self.get(pos).is_some() {
-                return Some(pos);
+        // without a target the edge square is on the path as well
+        let (target, path) = match to_pos {
+            Some(target) => (target, between(from_pos.index(), target.index())),
+            None => {
+                let mut edge = from_pos;
+                while self.pos_on_board(Position::new(edge.row + row_offset, edge.col + col_offset))
+                {
+                    edge = Position::new(edge.row + row_offset, edge.col + col_offset);
+                }
+                let path = between(from_pos.index(), edge.index());
+                (
+                    edge,
+                    if edge == from_pos {
+                        0
+                    } else {
+                        path | edge.bit()
+                    },
+                )
             }
+        };
 
-            path_row += row_offset;
-            path_col += col_offset;
+        let blockers = path & self.occupied();
+        if blockers == 0 {
+            return None;
         }
-
-        None
+        // the one closest to from_pos
+        let index = if target.index() > from_pos.index() {
+            blockers.trailing_zeros()
+        } else {
+            63 - blockers.leading_zeros()
+        };
+        Some(Position::from_index(index as usize))
     }
 
-    /// Sends rays from pos out in all possible directions and check Horse movement aswell
-    /// if it hits a piece, check piece and if its movement is legal
+    /// Is pos attacked by the side that is not to move
     pub fn is_square_attacked(&self, pos: Position) -> bool {
+        if !self.pos_on_board(pos) {
+            return false;
+        }
+        let them = self.move_turn.opposite();
+        self.attackers_to(pos.index(), self.occupied(), them) != 0
+    }
+
+    /// The pieces of `color` attacking `square` if the occupancy was `occupied`.
+    /// Looks from the square with every piece's attack pattern and checks if it
+    /// hits that kind of piece. Pieces missing from `occupied` still count, mask
+    /// them out if they are captured.
+    pub fn attackers_to(&self, square: usize, occupied: Bitboard, color: Color) -> Bitboard {
         use PieceType::*;
 
-        // check for queen bishop and rooks
-        for row_offset in -1..=1 {
-            for col_offset in -1..=1 {
-                if row_offset == 0 && col_offset == 0 {
-                    continue;
-                }
+        let queens = self.pieces(color, Queen);
+        // a pawn of the other colour on square attacks exactly the squares
+        // color's pawns attack square from
+        (pawn_attacks(color.opposite(), square) & self.pieces(color, Pawn))
+            | (KNIGHT_ATTACKS[square] & self.pieces(color, Knight))
+            | (KING_ATTACKS[square] & self.pieces(color, King))
+            | (bishop_attacks(square, occupied) & (self.pieces(color, Bishop) | queens))
+            | (rook_attacks(square, occupied) & (self.pieces(color, Rook) | queens))
+    }
 
-                if let Some(hit_pos) = self.check_clear_path(pos, None, row_offset, col_offset)
-                    && let Some(hit_piece) = self.get(hit_pos)
-                {
-                    let (d_row, d_col) = hit_pos.delta(pos);
-
-                    if hit_piece.color != self.move_turn {
-                        match hit_piece.piece_type {
-                            Bishop | Queen | Rook => {
-                                if hit_piece.move_shape_ok(d_row, d_col, false, hit_pos.row) {
-                                    return true;
-                                }
-                            }
-                            Pawn | King => {
-                                if hit_piece.move_shape_ok(d_row, d_col, true, hit_pos.row) {
-                                    return true;
-                                }
-                            }
-                            _ => continue,
-                        }
-                    }
-                }
+    /// Finds the checking pieces and the pinned pieces of the side to move, so
+    /// every move after that can be tested without making it
+    pub fn check_info(&self) -> CheckInfo {
+        use PieceType::*;
+
+        let us = self.move_turn;
+        let them = us.opposite();
+        let king = match us {
+            Color::White => self.white_king,
+            Color::Black => self.black_king,
+        }
+        .expect("validated: king position set")
+        .index();
+        let occupied = self.occupied();
+
+        let checkers = self.attackers_to(king, occupied, them);
+
+        // enemy sliders that would see the king on an empty board, one own piece
+        // in between means it is pinned
+        let queens = self.pieces(them, Queen);
+        let snipers = (rook_attacks(king, 0) & (self.pieces(them, Rook) | queens))
+            | (bishop_attacks(king, 0) & (self.pieces(them, Bishop) | queens));
+        let mut pinned = 0;
+        for sniper in Squares(snipers) {
+            let blockers = between(king, sniper.index()) & occupied;
+            if blockers.count_ones() == 1 {
+                pinned |= blockers & self.occupancy[us.index()];
             }
         }
 
-        // King, pawn and bishop has to_pos be a little different
-        const KNIGHT_MOV: [(i8, i8); 8] = [
-            (2, 1),
-            (2, -1),
-            (-2, 1),
-            (-2, -1),
-            (1, 2),
-            (1, -2),
-            (-1, 2),
-            (-1, -2),
-        ];
-
-        for (row_offset, col_offset) in KNIGHT_MOV {
-            let hit_pos = Position {
-                row: pos.row + row_offset,
-                col: pos.col + col_offset,
-            };
+        let check_mask = match checkers.count_ones() {
+            0 => !0,
+            1 => checkers | between(king, checkers.trailing_zeros() as usize),
+            _ => 0,
+        };
 
-            if self.pos_on_board(hit_pos)
-                && let Some(hit_piece) = self.get(hit_pos)
-                && hit_piece.color != self.move_turn
-                && hit_piece.piece_type == Knight
-            {
-                return true;
-            }
+        CheckInfo {
+            king,
+            checkers,
+            pinned,
+            check_mask,
         }
-
-        false
     }
 
-    /// This functions checks if a move will give check.
-    /// We try to do the move in a clone and checks if king is attacked
-    pub fn move_in_check(
+    /// Does the move leave the own king safe. `info` has to come from check_info
+    /// on this position, the move has to be legal apart from checks.
+    pub fn is_king_safe_after(
         &self,
+        info: &CheckInfo,
         from_pos: Position,
         to_pos: Position,
         move_type: MoveType,
-    ) -> Result<(), MoveError> {
-        let mut board_clone = self.clone();
-        let from_piece = board_clone
-            .get(from_pos)
-            .expect("validated: from_pos has piece");
+    ) -> bool {
+        let them = self.move_turn.opposite();
+        let (from, to) = (from_pos.index(), to_pos.index());
 
-        if move_type == MoveType::EnPassant {
-            let en_passanted_pos = Position {
-                row: (from_pos.row),
-                col: (to_pos.col),
-            };
-            board_clone.set(en_passanted_pos, None);
+        if from == info.king {
+            // castle_is_legal already looked at every square the king crosses
+            if move_type == MoveType::Castle {
+                return true;
+            }
+            // the king can not hide behind itself from a slider
+            let occupied = self.occupied() & !from_pos.bit();
+            return self.attackers_to(to, occupied, them) & !to_pos.bit() == 0;
         }
 
-        if move_type == MoveType::Castle {
-            let rook_from = board_clone.find_rook(from_pos, to_pos);
-            let rook_to = Position {
-                row: from_pos.row,
-                col: from_pos.col + (to_pos.col - from_pos.col).signum(),
-            };
-            let rook_piece = board_clone.get(rook_from).expect("validated: rook_from");
-            board_clone.set(rook_from, None);
-            board_clone.set(rook_to, Some(rook_piece));
+        if move_type == MoveType::EnPassant {
+            // two pawns leave the same rank at once, the pin check can not see
+            // that, so look at the board as it would be after the move
+            let captured = Position::new(from_pos.row, to_pos.col).bit();
+            let occupied = (self.occupied() & !from_pos.bit() & !captured) | to_pos.bit();
+            return self.attackers_to(info.king, occupied, them) & !captured == 0;
         }
 
-        board_clone.set(from_pos, None);
-        board_clone.set(to_pos, Some(from_piece));
+        if info.check_mask & to_pos.bit() == 0 {
+            return false;
+        }
+        info.pinned & from_pos.bit() == 0 || line(info.king, from) & to_pos.bit() != 0
+    }
 
-        let king_pos = if from_piece.piece_type == PieceType::King {
-            to_pos
+    /// Checks if a move would leave the own king in check
+    pub fn move_in_check(
+        &self,
+        from_pos: Position,
+        to_pos: Position,
+        move_type: MoveType,
+    ) -> Result<(), MoveError> {
+        if self.is_king_safe_after(&self.check_info(), from_pos, to_pos, move_type) {
+            Ok(())
         } else {
-            match from_piece.color {
-                Color::White => board_clone.white_king,
-                Color::Black => board_clone.black_king,
-            }
-            .expect("validated: king position set")
-        };
-
-        if board_clone.is_square_attacked(king_pos) {
-            return Err(MoveError::SelfCheck);
+            Err(MoveError::SelfCheck)
         }
-
-        Ok(())
     }
 
     /// When a move is legal, we need to set alot of values.
@@ -454,7 +565,10 @@ impl Board {
     /// kings position if changed
     /// has moved, for the moved piece
     /// remove pieces if capture (happens automatically if not en passant)
+    /// castling rights, halfmove clock and fullmove number
     /// switch move_turn
+    /// the zobrist key
+    /// everything needed to take the move back is pushed to the history
     pub fn set_values(
         &mut self,
         from_pos: Position,
@@ -467,6 +581,18 @@ impl Board {
 
         let set_ep = matches!(from_piece.piece_type, PieceType::Pawn) && dr.abs() == 2 && dc == 0;
 
+        let mut undo = Undo {
+            mv: self.build_move(from_pos, to_pos, move_type, prom_piece_type),
+            castle_rook: None,
+            en_passant: self.en_passant,
+            castling: self.castling,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            zobrist: self.zobrist,
+        };
+        // the pieces are updated by set, the rest is swapped at the end
+        self.zobrist ^= self.en_passant_zobrist() ^ zobrist::castling(self.castling);
+
         if move_type == MoveType::EnPassant {
             let en_passanted_pos = Position {
                 row: (from_pos.row),
@@ -482,6 +608,7 @@ impl Board {
                 col: from_pos.col + dc.signum(),
             };
             let mut rook_piece = self.get(rook_from).expect("validated: rook_from has piece");
+            undo.castle_rook = Some(rook_piece);
             rook_piece.has_moved = true;
             self.set(rook_from, None);
             self.set(rook_to, Some(rook_piece))
@@ -496,6 +623,28 @@ impl Board {
         from_piece.has_moved = true;
         self.set(to_pos, Some(from_piece));
 
+        if from_piece.piece_type == PieceType::King {
+            self.castling.remove_color(from_piece.color);
+        }
+        self.castling.remove_corner(from_pos);
+        self.castling.remove_corner(to_pos);
+
+        let resets_clock = match move_type {
+            MoveType::Normal { is_capture } => {
+                is_capture || from_piece.piece_type == PieceType::Pawn
+            }
+            MoveType::Castle => false,
+            MoveType::EnPassant | MoveType::PawnPromotion { .. } => true,
+        };
+        if resets_clock {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.move_turn == Color::Black {
+            self.fullmove_number += 1;
+        }
+
         self.en_passant = if set_ep {
             Some(Position {
                 row: to_pos.row - dr.signum(),
@@ -509,30 +658,101 @@ impl Board {
             Color::White => Color::Black,
             Color::Black => Color::White,
         };
+        self.zobrist ^=
+            self.en_passant_zobrist() ^ zobrist::castling(self.castling) ^ zobrist::black_to_move();
+
+        self.history.push(undo);
     }
 
-    pub fn is_check_mate(&self) -> bool {
+    /// Takes back the last move made with move_piece, returns what was undone
+    /// or None if there is no move to take back
+    pub fn unmake_move(&mut self) -> Option<Undo> {
+        let undo = self.history.pop()?;
+        let mv = undo.mv;
+
+        self.set(mv.to, None);
+        if let (Some(pos), Some(piece)) = (mv.capture_square(), mv.captured) {
+            self.set(pos, Some(piece));
+        }
+        if let (Some((rook_from, rook_to)), Some(rook_piece)) = (mv.castle_rook(), undo.castle_rook)
+        {
+            self.set(rook_to, None);
+            self.set(rook_from, Some(rook_piece));
+        }
+        self.set(mv.from, Some(mv.piece));
+
+        self.en_passant = undo.en_passant;
+        self.castling = undo.castling;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+        self.move_turn = mv.piece.color;
+        self.zobrist = undo.zobrist;
+
+        Some(undo)
+    }
+
+    /// is the king of the side to move attacked
+    pub fn is_check(&self) -> bool {
         let king_pos = match self.move_turn {
             Color::White => self.white_king.expect("validated: white king position set"),
             Color::Black => self.black_king.expect("validated: black king position set"),
         };
 
-        self.is_square_attacked(king_pos) && all_legal_moves(self).is_empty()
+        self.is_square_attacked(king_pos)
+    }
+
+    pub fn is_check_mate(&self) -> bool {
+        self.is_check() && all_legal_moves(self).is_empty()
     }
 
     pub fn is_stale_mate(&self) -> bool {
-        let king_pos = match self.move_turn {
-            Color::White => self.white_king.expect("validated: white king position set"),
-            Color::Black => self.black_king.expect("validated: black king position set"),
-        };
+        !self.is_check() && all_legal_moves(self).is_empty()
+    }
+
+    /// Neither side can ever mate, like K vs K, K+B vs K, K+N vs K or only
+    /// bishops on the same square colour
+    pub fn is_insufficient_material(&self) -> bool {
+        !self.can_mate(Color::White) && !self.can_mate(Color::Black)
+    }
 
-        !self.is_square_attacked(king_pos) && all_legal_moves(self).is_empty()
+    /// If `color` could still mate with some series of legal moves, used to
+    /// decide if running out of time is a loss or a draw
+    pub fn can_mate(&self, color: Color) -> bool {
+        use PieceType::*;
+
+        let them = color.opposite();
+        let has = |color: Color, piece_type: PieceType| self.pieces(color, piece_type) != 0;
+
+        if has(color, Pawn) || has(color, Rook) || has(color, Queen) {
+            return true;
+        }
+
+        if has(color, Knight) {
+            // a lone knight mates only if the other side has pieces that can
+            // block its own king in, queens never end up doing that
+            let own_minors = self.pieces(color, Knight) | self.pieces(color, Bishop);
+            let blockers =
+                self.occupancy[them.index()] & !self.pieces(them, King) & !self.pieces(them, Queen);
+            return own_minors.count_ones() > 1 || blockers != 0;
+        }
+
+        if has(color, Bishop) {
+            let bishops = self.pieces(Color::White, Bishop) | self.pieces(Color::Black, Bishop);
+            let same_color = bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0;
+            let pawns_or_knights = [Color::White, Color::Black]
+                .into_iter()
+                .any(|c| has(c, Pawn) || has(c, Knight));
+            return !same_color || pawns_or_knights;
+        }
+
+        false
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::board::{CastlingRights, PositionKey};
     use crate::pieces::{Color, Piece, PieceType};
 
     fn create_test_board() -> Board {
@@ -649,6 +869,44 @@ mod tests {
         assert_eq!(result, Err(MoveError::SelfCheck));
     }
 
+    #[test]
+    fn test_check_info_pins_and_checks() {
+        // the e2 knight is pinned by the e8 rook, the b4 bishop gives check
+        let board = Board::from_fen("4r1k1/8/8/8/1b6/8/4N3/4K3 w - - 0 1").unwrap();
+        let info = board.check_info();
+        assert_eq!(info.king, Position::E1.index());
+        assert_eq!(info.pinned, Position::E2.bit());
+        assert_eq!(info.checkers, Position::B4.bit());
+        assert_eq!(
+            info.check_mask,
+            Position::B4.bit() | Position::C3.bit() | Position::D2.bit()
+        );
+        // the pinned knight could block on c3 but not without opening the file
+        assert_eq!(
+            board.check_move(Position::E2, Position::C3),
+            Err(MoveError::SelfCheck)
+        );
+
+        let board = Board::from_fen("4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1").unwrap();
+        let info = board.check_info();
+        assert_eq!(info.checkers.count_ones(), 2);
+        assert!(board.legal_moves().iter().all(|mv| mv.from == Position::E1));
+    }
+
+    #[test]
+    fn test_en_passant_discovered_check() {
+        // bxc6 takes both pawns off the fifth rank and opens it for the h5 rook
+        let board = Board::from_fen("8/8/8/KPp4r/8/8/8/4k3 w - c6 0 1").unwrap();
+        assert_eq!(
+            board.check_move(Position::B5, Position::C6),
+            Err(MoveError::SelfCheck)
+        );
+        assert!(!board.legal_moves().iter().any(|mv| mv.is_en_passant()));
+
+        let board = Board::from_fen("8/8/8/1Pp4r/K7/8/8/4k3 w - c6 0 1").unwrap();
+        assert!(board.legal_moves().iter().any(|mv| mv.is_en_passant()));
+    }
+
     #[test]
     fn test_castle_king_has_moved() {
         let mut board = create_test_board();
@@ -656,15 +914,9 @@ mod tests {
         let to_pos = Position { row: 0, col: 6 };
         let rook_pos = Position { row: 0, col: 7 };
 
-        board.set(
-            from_pos,
-            Some(Piece {
-                piece_type: PieceType::King,
-                color: Color::White,
-                has_moved: true,
-            }),
-        );
+        setup_piece(&mut board, from_pos, PieceType::King, Color::White);
         setup_piece(&mut board, rook_pos, PieceType::Rook, Color::White);
+        board.castling = CastlingRights::none();
 
         let result = board.castle_is_legal(from_pos, to_pos);
         assert_eq!(result, Err(MoveError::KingHasMoved));
@@ -678,19 +930,80 @@ mod tests {
         let rook_pos = Position { row: 0, col: 7 };
 
         setup_piece(&mut board, from_pos, PieceType::King, Color::White);
-        board.set(
-            rook_pos,
-            Some(Piece {
-                piece_type: PieceType::Rook,
-                color: Color::White,
-                has_moved: true,
-            }),
-        );
+        setup_piece(&mut board, rook_pos, PieceType::Rook, Color::White);
+        board.castling.white_queen_side = true;
 
         let result = board.castle_is_legal(from_pos, to_pos);
         assert_eq!(result, Err(MoveError::RookHasMoved));
     }
 
+    #[test]
+    fn test_castle_follows_fen_rights() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1").unwrap();
+        let king_pos = Position { row: 0, col: 4 };
+
+        assert!(
+            board
+                .castle_is_legal(king_pos, Position { row: 0, col: 6 })
+                .is_ok()
+        );
+        assert_eq!(
+            board.castle_is_legal(king_pos, Position { row: 0, col: 2 }),
+            Err(MoveError::RookHasMoved)
+        );
+    }
+
+    #[test]
+    fn test_castle_rights_lost_when_rook_captured() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1").unwrap();
+        let rook_pos = Position { row: 0, col: 7 };
+        board
+            .move_piece(Position { row: 1, col: 6 }, rook_pos, None)
+            .unwrap();
+        assert!(!board.castling.white_king_side);
+
+        // a fresh rook on the home square does not bring the right back
+        board.set(rook_pos, Some(Piece::new(PieceType::Rook, Color::White)));
+        assert_eq!(
+            board.move_piece(
+                Position { row: 0, col: 4 },
+                Position { row: 0, col: 6 },
+                None
+            ),
+            Err(MoveError::RookHasMoved)
+        );
+    }
+
+    #[test]
+    fn test_castle_rights_lost_when_king_or_rook_moves() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        board
+            .move_piece(
+                Position { row: 0, col: 0 },
+                Position { row: 1, col: 0 },
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            board.castling,
+            CastlingRights {
+                white_king_side: true,
+                white_queen_side: false,
+                black_king_side: true,
+                black_queen_side: true,
+            }
+        );
+
+        board
+            .move_piece(
+                Position { row: 7, col: 4 },
+                Position { row: 7, col: 5 },
+                None,
+            )
+            .unwrap();
+        assert!(!board.castling.has_any(Color::Black));
+    }
+
     #[test]
     fn test_is_castle_true() {
         let mut board = create_test_board();
@@ -781,4 +1094,229 @@ mod tests {
         let result = board.game_over();
         assert_eq!(result, Some(GameResult::Checkmate(Color::Black)));
     }
+
+    #[test]
+    fn test_game_over_move_rules() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80").unwrap();
+        assert_eq!(board.game_over(), None);
+
+        board.play_san("Ra2").unwrap();
+        assert_eq!(board.halfmove_clock, 100);
+        let result = board.game_over().unwrap();
+        assert_eq!(result, GameResult::FiftyMoveRule);
+        assert!(result.is_claimable());
+
+        // a pawn move resets the clock
+        board.unmake_move();
+        board.play_san("e4").unwrap();
+        assert_eq!(board.halfmove_clock, 0);
+        assert_eq!(board.game_over(), None);
+
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 149 100").unwrap();
+        board.play_san("Ra2").unwrap();
+        let result = board.game_over().unwrap();
+        assert_eq!(result, GameResult::SeventyFiveMoveRule);
+        assert!(!result.is_claimable());
+    }
+
+    fn play_sans(board: &mut Board, sans: &str) {
+        for san in sans.split_whitespace() {
+            board.play_san(san).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_zobrist_transpositions() {
+        let start = Board::start_pos();
+        let mut board = start.clone();
+        play_sans(&mut board, "Nf3 Nf6 Ng1 Ng8");
+        assert_eq!(board.zobrist, start.zobrist);
+
+        let (mut a, mut b) = (start.clone(), start.clone());
+        play_sans(&mut a, "e4 e5 Nf3");
+        play_sans(&mut b, "Nf3 e5 e4");
+        // b has e3 as en passant square, but no black pawn can take there
+        assert_eq!(b.en_passant, Some(Position::E3));
+        assert_eq!(a.zobrist, b.zobrist);
+
+        // same placement, but white has lost the king side castling right
+        play_sans(&mut board, "Nf3 Nf6 Rg1 Ng8 Rh1 Nf6 Ng1 Ng8");
+        assert_eq!(board.position_key().bitboards, start.bitboards);
+        assert_ne!(board.zobrist, start.zobrist);
+        assert_eq!(board.zobrist, board.compute_zobrist());
+    }
+
+    #[test]
+    fn test_board_eq_and_hash_use_the_position() {
+        use std::collections::HashSet;
+
+        let (mut a, mut b) = (Board::start_pos(), Board::start_pos());
+        play_sans(&mut a, "e4 e5 Nf3");
+        play_sans(&mut b, "Nf3 e5 e4");
+        assert_eq!(a, b);
+        assert_ne!(a.history, b.history);
+        let positions: HashSet<Board> = [a.clone(), b].into_iter().collect();
+        assert_eq!(positions.len(), 1);
+
+        let key_positions: HashSet<PositionKey> = [a.position_key()].into_iter().collect();
+        assert!(key_positions.contains(&a.position_key()));
+
+        // same placement, but white can no longer castle
+        let mut c = Board::start_pos();
+        play_sans(&mut c, "Nf3 Nf6 Rg1 Ng8 Rh1 Nf6 Ng1 Ng8");
+        assert_ne!(c, Board::start_pos());
+    }
+
+    #[test]
+    fn test_threefold_and_fivefold_repetition() {
+        let mut board = Board::start_pos();
+        play_sans(&mut board, "Nf3 Nf6 Ng1 Ng8");
+        assert_eq!(board.repetition_count(), 2);
+        assert_eq!(board.game_over(), None);
+
+        play_sans(&mut board, "Nf3 Nf6 Ng1 Ng8");
+        let result = board.game_over().unwrap();
+        assert_eq!(result, GameResult::ThreefoldRepetition);
+        assert!(result.is_claimable());
+
+        play_sans(&mut board, "Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1 Ng8");
+        let result = board.game_over().unwrap();
+        assert_eq!(result, GameResult::FivefoldRepetition);
+        assert!(!result.is_claimable());
+    }
+
+    #[test]
+    fn test_repetition_castling_rights() {
+        // same placement after the kings walk back, but the castling rights are gone
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        play_sans(&mut board, "Ke2 Ke7 Ke1 Ke8");
+        assert_eq!(board.repetition_count(), 1);
+
+        play_sans(&mut board, "Ke2 Ke7 Ke1 Ke8");
+        assert_eq!(board.repetition_count(), 2);
+        assert_eq!(board.game_over(), None);
+
+        play_sans(&mut board, "Ke2 Ke7 Ke1 Ke8");
+        assert_eq!(board.game_over(), Some(GameResult::ThreefoldRepetition));
+    }
+
+    #[test]
+    fn test_repetition_en_passant() {
+        // e3 can be taken by the d4 pawn, so the position right after e4 is not
+        // the same as the one after the kings walk back
+        let mut board = Board::from_fen("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1").unwrap();
+        play_sans(&mut board, "e4");
+        assert_eq!(board.position_key().en_passant, Some(Position::E3));
+        play_sans(&mut board, "Kd7 Kd2 Ke8 Ke1 Kd7 Kd2 Ke8 Ke1");
+        assert_eq!(board.repetition_count(), 2);
+        assert_eq!(board.game_over(), None);
+
+        // nothing can take on e3, the en passant square does not count
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        play_sans(&mut board, "e4");
+        assert_eq!(board.position_key().en_passant, None);
+        play_sans(&mut board, "Kd7 Kd2 Ke8 Ke1 Kd7 Kd2 Ke8 Ke1");
+        assert_eq!(board.game_over(), Some(GameResult::ThreefoldRepetition));
+
+        // a pinned pawn can not take en passant either
+        let mut board = Board::from_fen("3k4/8/8/8/3p4/8/4P3/3R3K w - - 0 1").unwrap();
+        play_sans(&mut board, "e4");
+        assert_eq!(board.en_passant, Some(Position::E3));
+        assert_eq!(board.position_key().en_passant, None);
+        // the king stays on the d file so the pawn stays pinned
+        play_sans(&mut board, "Kd7 Kg1 Kd8 Kh1 Kd7 Kg1 Kd8 Kh1");
+        assert_eq!(board.game_over(), Some(GameResult::ThreefoldRepetition));
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        for fen in [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+            "4k3/8/7b/8/8/8/8/2B1K3 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert!(board.is_insufficient_material(), "{fen}");
+            assert_eq!(board.game_over(), Some(GameResult::InsufficientMaterial));
+        }
+
+        for fen in [
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1",
+            // bishops on different square colours
+            "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert!(!board.is_insufficient_material(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_can_mate() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1").unwrap();
+        assert!(board.can_mate(Color::White));
+        assert!(!board.can_mate(Color::Black));
+
+        // a knight can mate a king that is boxed in by its own rook
+        let board = Board::from_fen("4k2r/8/8/8/8/8/8/1N2K3 w - - 0 1").unwrap();
+        assert!(board.can_mate(Color::White));
+        let board = Board::from_fen("4k2q/8/8/8/8/8/8/1N2K3 w - - 0 1").unwrap();
+        assert!(!board.can_mate(Color::White));
+
+        // bishop vs pawn is not a dead position
+        let board = Board::from_fen("4k3/4p3/8/8/8/8/8/2B1K3 w - - 0 1").unwrap();
+        assert!(board.can_mate(Color::White));
+        assert!(board.can_mate(Color::Black));
+    }
+
+    #[test]
+    fn test_checkmate_beats_move_rules() {
+        let mut board = Board::from_fen("7k/8/6K1/8/8/8/8/R7 w - - 149 100").unwrap();
+        board.play_san("Ra8#").unwrap();
+        assert_eq!(board.game_over(), Some(GameResult::Checkmate(Color::White)));
+    }
+
+    #[test]
+    fn test_unmake_move_restores_board() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let before = board.clone();
+
+        // castle, then a double push that allows en passant, then en passant
+        board.play((0, 4), (0, 6), None).unwrap();
+        board.play((6, 2), (4, 2), None).unwrap();
+        board.play((4, 3), (5, 2), None).unwrap();
+        assert_eq!(board.history.len(), 3);
+
+        let undo = board.unmake_move().unwrap();
+        assert_eq!(undo.mv.move_type, MoveType::EnPassant);
+        assert_eq!(undo.mv.capture_square(), Some(Position { row: 4, col: 2 }));
+        assert_eq!(board.en_passant, Some(Position { row: 5, col: 2 }));
+
+        board.unmake_move().unwrap();
+        board.unmake_move().unwrap();
+        assert_eq!(board, before);
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.moved, before.moved);
+        assert!(board.history.is_empty());
+        assert!(board.unmake_move().is_none());
+    }
+
+    #[test]
+    fn test_unmake_move_promotion_capture() {
+        let fen = "r3k3/1P6/8/8/8/8/8/4K3 w q - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let before = board.clone();
+
+        board.play((6, 1), (7, 0), Some(PieceType::Queen)).unwrap();
+        assert!(!board.castling.black_queen_side);
+
+        board.unmake_move().unwrap();
+        assert_eq!(board, before);
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.moved, before.moved);
+    }
 }
@@ -0,0 +1,474 @@
+use std::fmt;
+
+use crate::board::{Board, Position};
+use crate::moves::Move;
+use crate::pieces::PieceType;
+
+/// Why a SAN string could not be turned into a move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    Empty,
+    /// not written like a SAN move at all
+    Invalid(String),
+    /// a SAN move, but no legal move in the position matches it
+    Illegal(String),
+    /// more than one legal move matches it
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Empty => write!(f, "empty SAN move"),
+            SanError::Invalid(san) => write!(f, "invalid SAN move '{san}'"),
+            SanError::Illegal(san) => write!(f, "illegal move '{san}'"),
+            SanError::Ambiguous(san) => write!(f, "ambiguous move '{san}'"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+/// What a SAN string says about the move, before looking at the board
+struct SanParts {
+    piece_type: PieceType,
+    from_col: Option<i8>,
+    from_row: Option<i8>,
+    is_capture: bool,
+    to: Position,
+    promotion: Option<PieceType>,
+}
+
+impl Board {
+    /// Writes a move in Standard Algebraic Notation, like "Nbd7", "exd6", "O-O-O",
+    /// "e8=Q+" or "Qxf7#". En passant is written as a normal pawn capture, like
+    /// PGN wants it, use to_san_ep for "exd6 e.p.".
+    ///
+    /// # Panics
+    /// if `mv` is not a legal move in this position
+    pub fn to_san(&self, mv: Move) -> String {
+        self.san(mv, false)
+    }
+
+    /// Same as to_san, but en passant gets " e.p." after it, before the check
+    /// mark: "exd6 e.p." or "exd6 e.p.+". parse_san reads both forms.
+    ///
+    /// # Panics
+    /// if `mv` is not a legal move in this position
+    pub fn to_san_ep(&self, mv: Move) -> String {
+        self.san(mv, true)
+    }
+
+    fn san(&self, mv: Move, en_passant_suffix: bool) -> String {
+        let mut san = if let Some((rook_from, _)) = mv.castle_rook() {
+            if rook_from.col > mv.from.col {
+                "O-O".to_string()
+            } else {
+                "O-O-O".to_string()
+            }
+        } else {
+            self.san_without_suffix(mv)
+        };
+        if en_passant_suffix && mv.is_en_passant() {
+            san.push_str(" e.p.");
+        }
+
+        let mut next = self.clone_position();
+        next.play_move(mv).expect("validated: mv is legal");
+        // only a check can be mate, so the moves are only generated then
+        if next.is_check() {
+            san.push(if next.legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+
+        san
+    }
+
+    fn san_without_suffix(&self, mv: Move) -> String {
+        let mut san = String::new();
+        let piece_type = mv.piece.piece_type;
+
+        if piece_type == PieceType::Pawn {
+            if mv.is_capture() {
+                san.push(file_char(mv.from.col));
+            }
+        } else {
+            san.push(piece_type.letter());
+            san.push_str(&self.disambiguation(mv));
+        }
+
+        if mv.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(promotion.letter());
+        }
+
+        san
+    }
+
+    /// The file, rank or whole square of the from position, only as much as
+    /// needed to tell mv apart from other moves of the same piece type to the same square
+    fn disambiguation(&self, mv: Move) -> String {
+        let rivals: Vec<Move> = self
+            .legal_moves()
+            .into_iter()
+            .filter(|other| {
+                other.to == mv.to
+                    && other.from != mv.from
+                    && other.piece.piece_type == mv.piece.piece_type
+            })
+            .collect();
+
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|other| other.from.col != mv.from.col) {
+            file_char(mv.from.col).to_string()
+        } else if rivals.iter().all(|other| other.from.row != mv.from.row) {
+            rank_char(mv.from.row).to_string()
+        } else {
+            mv.from.to_string()
+        }
+    }
+}
+
+impl Board {
+    /// Finds the legal move a SAN string describes. Check and annotation marks are
+    /// optional, and "0-0", "e8Q" and "e8=q" are accepted as well.
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let trimmed = san
+            .trim()
+            .trim_end_matches(['+', '#', '!', '?'])
+            .trim_end_matches("e.p.")
+            .trim_end();
+        if trimmed.is_empty() {
+            return Err(SanError::Empty);
+        }
+
+        let legal_moves = self.legal_moves();
+        let candidates: Vec<Move> = match trimmed {
+            "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+                let king_side = trimmed.len() == 3;
+                legal_moves
+                    .into_iter()
+                    .filter(|mv| mv.is_castle() && (mv.to.col > mv.from.col) == king_side)
+                    .collect()
+            }
+            _ => {
+                let parts =
+                    parse_san_parts(trimmed).ok_or_else(|| SanError::Invalid(san.to_string()))?;
+                legal_moves
+                    .into_iter()
+                    .filter(|mv| parts.matches(mv))
+                    .collect()
+            }
+        };
+
+        match candidates.as_slice() {
+            [] => Err(SanError::Illegal(san.to_string())),
+            [mv] => Ok(*mv),
+            _ => Err(SanError::Ambiguous(san.to_string())),
+        }
+    }
+
+    /// Parses a SAN move and plays it
+    pub fn play_san(&mut self, san: &str) -> Result<Move, SanError> {
+        let mv = self.parse_san(san)?;
+        self.play_move(mv).expect("validated: parsed move is legal");
+        Ok(mv)
+    }
+}
+
+impl SanParts {
+    fn matches(&self, mv: &Move) -> bool {
+        mv.piece.piece_type == self.piece_type
+            && mv.to == self.to
+            && mv.promotion == self.promotion
+            && !mv.is_castle()
+            && self.capture_matches(mv)
+            && self.from_col.is_none_or(|col| mv.from.col == col)
+            && self.from_row.is_none_or(|row| mv.from.row == row)
+    }
+
+    /// A piece move may leave out the x, a pawn capture needs it or "d5" could
+    /// turn into exd5
+    fn capture_matches(&self, mv: &Move) -> bool {
+        if self.piece_type == PieceType::Pawn {
+            mv.is_capture() == self.is_capture
+        } else {
+            !self.is_capture || mv.is_capture()
+        }
+    }
+}
+
+/// Splits "Nbxd7", "exd5" or "e8=Q" into its parts, read from the back
+fn parse_san_parts(san: &str) -> Option<SanParts> {
+    let mut chars: Vec<char> = san.chars().collect();
+
+    let piece_type = match chars.first() {
+        Some(&ch) if ch.is_ascii_uppercase() => {
+            chars.remove(0);
+            PieceType::from_letter(ch)?
+        }
+        _ => PieceType::Pawn,
+    };
+
+    let mut promotion = None;
+    if let [.., before, last] = chars.as_slice()
+        && (*before == '=' || before.is_ascii_digit())
+        && let Some(promoted) = PieceType::from_letter(*last)
+    {
+        if matches!(promoted, PieceType::Pawn | PieceType::King) {
+            return None;
+        }
+        promotion = Some(promoted);
+        chars.pop();
+        if chars.last() == Some(&'=') {
+            chars.pop();
+        }
+    }
+
+    let rank = chars.pop()?;
+    let file = chars.pop()?;
+    let to = Position {
+        row: parse_rank(rank)?,
+        col: parse_file(file)?,
+    };
+
+    let is_capture = chars.last() == Some(&'x');
+    if is_capture {
+        chars.pop();
+    }
+
+    let mut from_col = None;
+    let mut from_row = None;
+    match chars.as_slice() {
+        [] => {}
+        [ch] if parse_file(*ch).is_some() => from_col = parse_file(*ch),
+        [ch] if parse_rank(*ch).is_some() => from_row = parse_rank(*ch),
+        [file, rank] => {
+            from_col = Some(parse_file(*file)?);
+            from_row = Some(parse_rank(*rank)?);
+        }
+        _ => return None,
+    }
+
+    Some(SanParts {
+        piece_type,
+        from_col,
+        from_row,
+        is_capture,
+        to,
+        promotion,
+    })
+}
+
+fn parse_file(ch: char) -> Option<i8> {
+    ('a'..='h').contains(&ch).then(|| ch as i8 - b'a' as i8)
+}
+
+fn parse_rank(ch: char) -> Option<i8> {
+    ('1'..='8').contains(&ch).then(|| ch as i8 - b'1' as i8)
+}
+
+fn file_char(col: i8) -> char {
+    (b'a' + col as u8) as char
+}
+
+fn rank_char(row: i8) -> char {
+    (b'1' + row as u8) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Position;
+
+    fn san_of(fen: &str, from: (i8, i8), to: (i8, i8), promotion: Option<PieceType>) -> String {
+        let board = Board::from_fen(fen).unwrap();
+        let mv = board
+            .legal_moves()
+            .into_iter()
+            .find(|mv| {
+                mv.from == Position::new(from.0, from.1)
+                    && mv.to == Position::new(to.0, to.1)
+                    && mv.promotion == promotion
+            })
+            .unwrap();
+        board.to_san(mv)
+    }
+
+    #[test]
+    fn test_san_pawn_moves() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, (1, 4), (3, 4), None), "e4");
+        assert_eq!(san_of(start, (0, 6), (2, 5), None), "Nf3");
+
+        let ep = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(san_of(ep, (4, 4), (5, 3), None), "exd6");
+    }
+
+    #[test]
+    fn test_san_en_passant_suffix() {
+        let board = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let ep = board.parse_san("exd6").unwrap();
+        assert_eq!(board.to_san_ep(ep), "exd6 e.p.");
+        assert_eq!(board.parse_san("exd6 e.p."), Ok(ep));
+        let push = board.parse_san("e6").unwrap();
+        assert_eq!(board.to_san_ep(push), "e6");
+
+        // the check mark goes after the suffix
+        let board = Board::from_fen("8/2k5/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let ep = board.parse_san("exd6").unwrap();
+        assert_eq!(board.to_san(ep), "exd6+");
+        assert_eq!(board.to_san_ep(ep), "exd6 e.p.+");
+        assert_eq!(board.parse_san("exd6 e.p.+"), Ok(ep));
+    }
+
+    #[test]
+    fn test_san_disambiguation() {
+        let files = "rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(san_of(files, (7, 1), (6, 3), None), "Nbd7");
+        assert_eq!(san_of(files, (5, 5), (6, 3), None), "Nfd7");
+
+        let ranks = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san_of(ranks, (0, 0), (2, 0), None), "R1a3");
+        assert_eq!(san_of(ranks, (4, 0), (2, 0), None), "R5a3");
+
+        let squares = "2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1";
+        assert_eq!(san_of(squares, (3, 7), (0, 4), None), "Qh4e1");
+    }
+
+    #[test]
+    fn test_san_castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san_of(fen, (0, 4), (0, 6), None), "O-O");
+        assert_eq!(san_of(fen, (0, 4), (0, 2), None), "O-O-O");
+    }
+
+    #[test]
+    fn test_san_promotion_and_check() {
+        let fen = "k7/4P3/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san_of(fen, (6, 4), (7, 4), Some(PieceType::Queen)), "e8=Q+");
+        assert_eq!(san_of(fen, (6, 4), (7, 4), Some(PieceType::Knight)), "e8=N");
+    }
+
+    #[test]
+    fn test_san_checkmate() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        assert_eq!(san_of(fen, (4, 7), (6, 5), None), "Qxf7#");
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let board = Board::start_pos();
+        let mv = board.parse_san("Nf3").unwrap();
+        assert_eq!(mv.from, Position::new(0, 6));
+        assert_eq!(mv.to, Position::new(2, 5));
+        assert_eq!(board.parse_san("e4").unwrap().to, Position::new(3, 4));
+
+        let captures = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        let board = Board::from_fen(captures).unwrap();
+        let mv = board.parse_san("exd5").unwrap();
+        assert!(mv.is_capture());
+        assert_eq!(board.parse_san("exd5!?").unwrap(), mv);
+    }
+
+    #[test]
+    fn test_parse_san_castling_variants() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        for san in ["O-O", "0-0", "O-O+"] {
+            assert_eq!(
+                board.parse_san(san).unwrap().to,
+                Position::new(0, 6),
+                "{san}"
+            );
+        }
+        for san in ["O-O-O", "0-0-0"] {
+            assert_eq!(
+                board.parse_san(san).unwrap().to,
+                Position::new(0, 2),
+                "{san}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_san_promotion_variants() {
+        let board = Board::from_fen("1n5k/P3P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mv = board.parse_san("axb8=N").unwrap();
+        assert_eq!(mv.promotion, Some(PieceType::Knight));
+        assert!(mv.is_capture());
+
+        for san in ["e8=Q+", "e8Q", "e8=q", "e8q"] {
+            assert_eq!(
+                board.parse_san(san).unwrap().promotion,
+                Some(PieceType::Queen)
+            );
+        }
+        assert_eq!(
+            board.parse_san("a8b").unwrap().promotion,
+            Some(PieceType::Bishop)
+        );
+        assert_eq!(
+            board.parse_san("a8"),
+            Err(SanError::Illegal("a8".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_san_missing_check_mark() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.parse_san("Qxf7"), board.parse_san("Qxf7#"));
+        assert_eq!(board.to_san(board.parse_san("Qxf7").unwrap()), "Qxf7#");
+    }
+
+    #[test]
+    fn test_parse_san_errors() {
+        let board = Board::from_fen("rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1")
+            .unwrap();
+        assert_eq!(board.parse_san(""), Err(SanError::Empty));
+        assert_eq!(
+            board.parse_san("Nd7"),
+            Err(SanError::Ambiguous("Nd7".to_string()))
+        );
+        assert_eq!(board.parse_san("Nbd7").unwrap().from, Position::new(7, 1));
+        assert_eq!(board.parse_san("N8d7").unwrap().from, Position::new(7, 1));
+        assert_eq!(
+            board.parse_san("Ke3"),
+            Err(SanError::Illegal("Ke3".to_string()))
+        );
+        assert_eq!(
+            board.parse_san("Zz9"),
+            Err(SanError::Invalid("Zz9".to_string()))
+        );
+        assert_eq!(
+            board.parse_san("Nxd7"),
+            Err(SanError::Illegal("Nxd7".to_string()))
+        );
+
+        // a pawn push that is blocked is not a capture
+        let board = Board::from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            board.parse_san("d5"),
+            Err(SanError::Illegal("d5".to_string()))
+        );
+        assert_eq!(board.parse_san("exd5").unwrap().from, Position::E4);
+    }
+
+    #[test]
+    fn test_san_round_trip() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        for mv in board.legal_moves() {
+            assert_eq!(board.parse_san(&board.to_san(mv)), Ok(mv));
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::board::{Board, Position};
+use crate::moves::Move;
+use crate::pieces::PieceType;
+use crate::rules::{MoveError, MoveType};
+
+/// Why a UCI move string like "e2e4" or "e7e8q" could not be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciError {
+    /// has to be 4 characters, or 5 with a promotion
+    WrongLength(String),
+    InvalidSquare(String),
+    InvalidPromotion(char),
+    /// a pawn reaches the last rank but no promotion letter was given
+    MissingPromotion,
+    /// a promotion letter on a move that is not a promotion
+    UnexpectedPromotion,
+    Illegal(MoveError),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciError::WrongLength(uci) => write!(f, "'{uci}' is not 4 or 5 characters"),
+            UciError::InvalidSquare(square) => write!(f, "invalid square '{square}'"),
+            UciError::InvalidPromotion(ch) => write!(f, "invalid promotion piece '{ch}'"),
+            UciError::MissingPromotion => write!(f, "promotion piece missing"),
+            UciError::UnexpectedPromotion => write!(f, "promotion piece on a non promotion move"),
+            UciError::Illegal(e) => write!(f, "illegal move: {e:?}"),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+/// A move in coordinate notation, the squares and promotion piece only.
+/// Castling is written as the king move, "e1g1".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UciMove {
+    pub from: Position,
+    pub to: Position,
+    pub promotion: Option<PieceType>,
+}
+
+impl FromStr for UciMove {
+    type Err = UciError;
+
+    fn from_str(uci: &str) -> Result<Self, Self::Err> {
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return Err(UciError::WrongLength(uci.to_string()));
+        }
+
+        let square = |s: &str| {
+            s.parse::<Position>()
+                .map_err(|_| UciError::InvalidSquare(s.to_string()))
+        };
+        let from = square(&uci[0..2])?;
+        let to = square(&uci[2..4])?;
+
+        let promotion = match uci[4..].chars().next() {
+            None => None,
+            Some(ch) => match ch.to_ascii_lowercase() {
+                'q' | 'r' | 'b' | 'n' => PieceType::from_letter(ch),
+                _ => return Err(UciError::InvalidPromotion(ch)),
+            },
+        };
+
+        Ok(UciMove {
+            from,
+            to,
+            promotion,
+        })
+    }
+}
+
+impl fmt::Display for UciMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(promotion) = self.promotion {
+            write!(f, "{}", promotion.letter().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Move> for UciMove {
+    fn from(mv: Move) -> Self {
+        UciMove {
+            from: mv.from,
+            to: mv.to,
+            promotion: mv.promotion,
+        }
+    }
+}
+
+impl Move {
+    /// The move in coordinate notation, like "e2e4" or "e7e8q"
+    pub fn to_uci(&self) -> String {
+        UciMove::from(*self).to_string()
+    }
+}
+
+impl Board {
+    /// Finds the legal move a UCI string describes
+    pub fn parse_uci(&self, uci: &str) -> Result<Move, UciError> {
+        let uci_move: UciMove = uci.parse()?;
+        let move_type = self
+            .check_move(uci_move.from, uci_move.to)
+            .map_err(UciError::Illegal)?;
+
+        match (move_type, uci_move.promotion) {
+            (MoveType::PawnPromotion { .. }, None) => return Err(UciError::MissingPromotion),
+            (MoveType::PawnPromotion { .. }, Some(_)) | (_, None) => {}
+            (_, Some(_)) => return Err(UciError::UnexpectedPromotion),
+        }
+
+        Ok(self.build_move(uci_move.from, uci_move.to, move_type, uci_move.promotion))
+    }
+
+    /// Parses a UCI move and plays it
+    pub fn play_uci(&mut self, uci: &str) -> Result<Move, UciError> {
+        let mv = self.parse_uci(uci)?;
+        self.play_move(mv).map_err(UciError::Illegal)?;
+        Ok(mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uci_move_from_str() {
+        let mv: UciMove = "e2e4".parse().unwrap();
+        assert_eq!(mv.from, Position::E2);
+        assert_eq!(mv.to, Position::E4);
+        assert_eq!(mv.promotion, None);
+
+        let mv: UciMove = "e7e8q".parse().unwrap();
+        assert_eq!(mv.promotion, Some(PieceType::Queen));
+        assert_eq!(mv.to_string(), "e7e8q");
+    }
+
+    #[test]
+    fn test_uci_move_errors() {
+        assert_eq!(
+            "e2e".parse::<UciMove>(),
+            Err(UciError::WrongLength("e2e".to_string()))
+        );
+        assert_eq!(
+            "e2e4qq".parse::<UciMove>(),
+            Err(UciError::WrongLength("e2e4qq".to_string()))
+        );
+        assert_eq!(
+            "e9e4".parse::<UciMove>(),
+            Err(UciError::InvalidSquare("e9".to_string()))
+        );
+        assert_eq!(
+            "e2i4".parse::<UciMove>(),
+            Err(UciError::InvalidSquare("i4".to_string()))
+        );
+        assert_eq!(
+            "e7e8k".parse::<UciMove>(),
+            Err(UciError::InvalidPromotion('k'))
+        );
+    }
+
+    #[test]
+    fn test_play_uci() {
+        let mut board = Board::start_pos();
+        let mv = board.play_uci("g1f3").unwrap();
+        assert_eq!(mv.piece.piece_type, PieceType::Knight);
+        assert_eq!(mv.to_uci(), "g1f3");
+
+        assert_eq!(
+            board.play_uci("e2e4"),
+            Err(UciError::Illegal(MoveError::WrongTurn))
+        );
+        assert_eq!(board.play_uci("e7e5q"), Err(UciError::UnexpectedPromotion));
+    }
+
+    #[test]
+    fn test_play_uci_castle_and_promotion() {
+        let mut board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(board.parse_uci("b7b8"), Err(UciError::MissingPromotion));
+
+        let castle = board.play_uci("e1g1").unwrap();
+        assert!(castle.is_castle());
+        assert_eq!(castle.to_uci(), "e1g1");
+
+        board.play_uci("e8d7").unwrap();
+        let promotion = board.play_uci("b7b8n").unwrap();
+        assert_eq!(promotion.promotion, Some(PieceType::Knight));
+        assert_eq!(promotion.to_uci(), "b7b8n");
+    }
+}
@@ -0,0 +1,91 @@
+use crate::board::{CastleSide, CastlingRights};
+use crate::pieces::{Color, PieceType};
+
+/// Random numbers xor-ed together into Board::zobrist, one per piece on a
+/// square, castling right and en passant file, plus one for black to move
+struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    castling: [u64; 4],
+    en_passant: [u64; 8],
+    black_to_move: u64,
+}
+
+/// splitmix64, fixed seed so the keys are the same every run
+const fn next(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+const fn generate() -> ZobristKeys {
+    let mut state = 0;
+    let mut pieces = [[[0; 64]; 6]; 2];
+    let mut color = 0;
+    while color < 2 {
+        let mut piece_type = 0;
+        while piece_type < 6 {
+            let mut square = 0;
+            while square < 64 {
+                pieces[color][piece_type][square] = next(&mut state);
+                square += 1;
+            }
+            piece_type += 1;
+        }
+        color += 1;
+    }
+
+    let mut castling = [0; 4];
+    let mut i = 0;
+    while i < 4 {
+        castling[i] = next(&mut state);
+        i += 1;
+    }
+    let mut en_passant = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+        en_passant[i] = next(&mut state);
+        i += 1;
+    }
+
+    ZobristKeys {
+        pieces,
+        castling,
+        en_passant,
+        black_to_move: next(&mut state),
+    }
+}
+
+static KEYS: ZobristKeys = generate();
+
+pub fn piece(color: Color, piece_type: PieceType, square: usize) -> u64 {
+    KEYS.pieces[color.index()][piece_type.index()][square]
+}
+
+/// All the rights that are set, xor the old and the new rights to update a key
+pub fn castling(rights: CastlingRights) -> u64 {
+    let mut key = 0;
+    for (i, (color, side)) in [
+        (Color::White, CastleSide::KingSide),
+        (Color::White, CastleSide::QueenSide),
+        (Color::Black, CastleSide::KingSide),
+        (Color::Black, CastleSide::QueenSide),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if rights.has(color, side) {
+            key ^= KEYS.castling[i];
+        }
+    }
+    key
+}
+
+pub fn en_passant(file: i8) -> u64 {
+    KEYS.en_passant[file as usize]
+}
+
+pub fn black_to_move() -> u64 {
+    KEYS.black_to_move
+}
@@ -3,12 +3,265 @@ mod tests {
     use hermanha_chess::*;
     const DEPTH: usize = 4;
 
+    const PERFT_FENS: [&str; 7] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    /// Deeper counts than DEPTH. Kiwipete and positions 5 and 6 stop at depth 5,
+    /// at depth 6 they are 3 to 8 billion nodes which is too slow for a test.
+    const DEEP_PERFT: [(&str, &[usize]); 7] = [
+        (PERFT_FENS[0], &[20, 400, 8902, 197281, 4865609, 119060324]),
+        (PERFT_FENS[1], &[48, 2039, 97862, 4085603, 193690690]),
+        (PERFT_FENS[2], &[14, 191, 2812, 43238, 674624, 11030083]),
+        (PERFT_FENS[3], &[6, 264, 9467, 422333, 15833292, 706045033]),
+        (PERFT_FENS[4], &[6, 264, 9467, 422333, 15833292, 706045033]),
+        (PERFT_FENS[5], &[44, 1486, 62379, 2103487, 89941194]),
+        (PERFT_FENS[6], &[46, 2079, 89890, 3894594, 164075551]),
+    ];
+
+    fn fen_round_trip_tree(board: &Board, depth: usize) {
+        let fen = board.to_fen();
+        let reparsed = Board::from_fen(&fen).unwrap();
+        assert_eq!(reparsed.to_fen(), fen);
+
+        if depth == 0 {
+            return;
+        }
+
+        let moves = board.legal_moves();
+        let squares = |moves: Vec<Move>| -> Vec<(Position, Position, Option<PieceType>)> {
+            moves
+                .into_iter()
+                .map(|mv| (mv.from, mv.to, mv.promotion))
+                .collect()
+        };
+        assert_eq!(
+            squares(reparsed.legal_moves()),
+            squares(moves.clone()),
+            "{fen}"
+        );
+        for mv in moves {
+            let mut next = board.clone();
+            next.play_move(mv).unwrap();
+            fen_round_trip_tree(&next, depth - 1);
+        }
+    }
+
+    fn make_unmake_tree(board: &mut Board, depth: usize) {
+        for mv in board.legal_moves() {
+            let before = board.clone();
+            board.play_move(mv).unwrap();
+            if depth > 1 {
+                make_unmake_tree(board, depth - 1);
+            }
+            board.unmake_move().unwrap();
+            // == only looks at the position, the rest has to come back too
+            assert_eq!(*board, before, "{mv:?} in {}", before.to_fen());
+            assert_eq!(board.to_fen(), before.to_fen());
+            assert_eq!(board.moved, before.moved);
+            assert_eq!(board.history, before.history);
+            assert_eq!(board.zobrist, before.zobrist);
+        }
+    }
+
+    /// nodes, captures, en passants, castles, promotions, checks, discovered
+    /// checks, double checks and checkmates, in the order of the tables on
+    /// https://www.chessprogramming.org/Perft_Results
+    fn stats(row: [usize; 9]) -> PerftStats {
+        let [
+            nodes,
+            captures,
+            en_passants,
+            castles,
+            promotions,
+            checks,
+            discovered_checks,
+            double_checks,
+            checkmates,
+        ] = row;
+        PerftStats {
+            nodes,
+            captures,
+            en_passants,
+            castles,
+            promotions,
+            checks,
+            discovered_checks,
+            double_checks,
+            checkmates,
+        }
+    }
+
+    const START_STATS: [[usize; 9]; 5] = [
+        [20, 0, 0, 0, 0, 0, 0, 0, 0],
+        [400, 0, 0, 0, 0, 0, 0, 0, 0],
+        [8902, 34, 0, 0, 0, 12, 0, 0, 0],
+        [197281, 1576, 0, 0, 0, 469, 0, 0, 8],
+        [4865609, 82719, 258, 0, 0, 27351, 6, 0, 347],
+    ];
+    const KIWIPETE_STATS: [[usize; 9]; 4] = [
+        [48, 8, 0, 2, 0, 0, 0, 0, 0],
+        [2039, 351, 1, 91, 0, 3, 0, 0, 0],
+        [97862, 17102, 45, 3162, 0, 993, 0, 0, 1],
+        [4085603, 757163, 1929, 128013, 15172, 25523, 42, 6, 43],
+    ];
+    const POS3_STATS: [[usize; 9]; 5] = [
+        [14, 1, 0, 0, 0, 2, 0, 0, 0],
+        [191, 14, 0, 0, 0, 10, 0, 0, 0],
+        [2812, 209, 2, 0, 0, 267, 3, 0, 0],
+        [43238, 3348, 123, 0, 0, 1680, 106, 0, 17],
+        [674624, 52051, 1165, 0, 0, 52950, 1292, 3, 0],
+    ];
+
+    fn check_stats(fen: &str, expected: &[[usize; 9]]) {
+        let mut board = Board::from_fen(fen).unwrap();
+        let expected: Vec<PerftStats> = expected.iter().map(|&row| stats(row)).collect();
+        assert_eq!(board.perft_stats(expected.len()), expected, "{fen}");
+    }
+
+    /// The key kept up to date move by move has to match the one computed from
+    /// scratch, also after taking the moves back
+    fn zobrist_tree(board: &mut Board, depth: usize) {
+        assert_eq!(board.zobrist, board.compute_zobrist(), "{}", board.to_fen());
+        if depth == 0 {
+            return;
+        }
+        for mv in board.legal_moves() {
+            let before = board.zobrist;
+            board.play_move(mv).unwrap();
+            zobrist_tree(board, depth - 1);
+            board.unmake_move().unwrap();
+            assert_eq!(board.zobrist, before);
+        }
+    }
+
+    /// The generator and check_move (used for moves typed in by a user) have to agree
+    fn generator_matches_check_move(board: &mut Board, depth: usize) {
+        let mut probed = Vec::new();
+        for from in 0..64 {
+            for to in 0..64 {
+                let (from, to) = (Position::from_index(from), Position::from_index(to));
+                if board.check_move(from, to).is_ok() {
+                    probed.push((from, to));
+                }
+            }
+        }
+        let mut generated: Vec<(Position, Position)> = board
+            .legal_moves()
+            .iter()
+            .map(|mv| (mv.from, mv.to))
+            .collect();
+        generated.sort_by_key(|(from, to)| (from.index(), to.index()));
+        generated.dedup();
+        assert_eq!(generated, probed, "{}", board.to_fen());
+
+        if depth > 1 {
+            for mv in board.legal_moves() {
+                board.play_move(mv).unwrap();
+                generator_matches_check_move(board, depth - 1);
+                board.unmake_move();
+            }
+        }
+    }
+
+    #[test]
+    fn test_generator_matches_check_move() {
+        for fen in PERFT_FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            generator_matches_check_move(&mut board, 2);
+        }
+    }
+
+    #[test]
+    fn test_make_unmake_perft_positions() {
+        for fen in PERFT_FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            make_unmake_tree(&mut board, 3);
+        }
+    }
+
+    #[test]
+    fn test_zobrist_perft_positions() {
+        for fen in PERFT_FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            zobrist_tree(&mut board, 3);
+        }
+    }
+
+    #[test]
+    fn test_perft_stats() {
+        check_stats(PERFT_FENS[0], &START_STATS[..3]);
+        check_stats(PERFT_FENS[1], &KIWIPETE_STATS[..3]);
+        check_stats(PERFT_FENS[2], &POS3_STATS[..4]);
+    }
+
+    #[test]
+    fn test_parallel_perft_matches_perft_layers() {
+        for fen in PERFT_FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            let expected = board.perft_layers(3);
+            for threads in [1, 4] {
+                assert_eq!(board.perft_layers_parallel(3, threads), expected, "{fen}");
+            }
+            assert_eq!(board.perft_layers_parallel(1, 4), &expected[..1]);
+            assert_eq!(board.perft_layers_parallel(2, 4), &expected[..2]);
+        }
+    }
+
+    #[test]
+    fn test_perft_stats_castling_check() {
+        // O-O-O checks with the rook, that is not a discovered check
+        let mut board = Board::from_fen("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        let stats = board.perft_stats(1)[0];
+        assert_eq!(stats.castles, 1);
+        assert_eq!(stats.discovered_checks, 0);
+        assert!(stats.checks > 0);
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        let mut board = Board::start_pos();
+        let divide = board.perft_divide(3);
+        assert_eq!(divide.len(), 20);
+        assert_eq!(divide.iter().map(|(_, n)| n).sum::<usize>(), 8902);
+        let count = |uci: &str| {
+            divide
+                .iter()
+                .find(|(mv, _)| mv.to_uci() == uci)
+                .map(|&(_, n)| n)
+        };
+        assert_eq!(count("e2e4"), Some(600));
+        assert_eq!(count("a2a3"), Some(380));
+        assert_eq!(count("g1f3"), Some(440));
+
+        for fen in PERFT_FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            let total: usize = board.perft_divide(2).iter().map(|(_, n)| n).sum();
+            assert_eq!(total, board.perft_layers(2)[1], "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_fen_round_trip_perft_positions() {
+        for fen in PERFT_FENS {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(board.to_fen(), fen);
+            fen_round_trip_tree(&board, 2);
+        }
+    }
+
     #[test]
     fn test_all_legal_moves_pos_1() {
-        let fen: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+        let fen: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
         let mut board = Board::default();
-        board.setup_fen(fen);
+        board.setup_fen(fen).unwrap();
 
         let expected: Vec<usize> = vec![20, 400, 8902, 197281, 4865609];
         let totals = board.perft_layers(DEPTH);
@@ -18,9 +271,9 @@ mod tests {
 
     #[test]
     fn test_all_legal_moves_pos_2() {
-        let fen: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R";
+        let fen: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
         let mut board = Board::default();
-        board.setup_fen(fen);
+        board.setup_fen(fen).unwrap();
 
         let expected: Vec<usize> = vec![48, 2039, 97862, 4085603, 193690690];
         let totals = board.perft_layers(DEPTH);
@@ -30,9 +283,9 @@ mod tests {
 
     #[test]
     fn test_all_legal_moves_pos_3() {
-        let fen: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8";
+        let fen: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
         let mut board = Board::default();
-        board.setup_fen(fen);
+        board.setup_fen(fen).unwrap();
 
         let expected: Vec<usize> = vec![14, 191, 2812, 43238];
         let totals = board.perft_layers(DEPTH);
@@ -42,9 +295,21 @@ mod tests {
 
     #[test]
     fn test_all_legal_moves_pos_4() {
-        let fen: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1";
+        let fen: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        let mut board = Board::default();
+        board.setup_fen(fen).unwrap();
+
+        let expected: Vec<usize> = vec![6, 264, 9467, 422333];
+        let totals = board.perft_layers(DEPTH);
+
+        assert_eq!(totals, expected[..DEPTH]);
+    }
+
+    #[test]
+    fn test_all_legal_moves_pos_4_mirrored() {
+        let fen: &str = "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
         let mut board = Board::default();
-        board.setup_fen(fen);
+        board.setup_fen(fen).unwrap();
 
         let expected: Vec<usize> = vec![6, 264, 9467, 422333];
         let totals = board.perft_layers(DEPTH);
@@ -54,9 +319,9 @@ mod tests {
 
     #[test]
     fn test_all_legal_moves_pos_5() {
-        let fen: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R";
+        let fen: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
         let mut board = Board::default();
-        board.setup_fen(fen);
+        board.setup_fen(fen).unwrap();
 
         let expected: Vec<usize> = vec![44, 1486, 62379, 2103487];
         let totals = board.perft_layers(DEPTH);
@@ -66,13 +331,46 @@ mod tests {
 
     #[test]
     fn test_all_legal_moves_pos_6() {
-        let fen: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1";
+        let fen: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
         let mut board = Board::default();
-        board.setup_fen(fen);
+        board.setup_fen(fen).unwrap();
 
         let expected: Vec<usize> = vec![46, 2079, 89890, 3894594];
         let totals = board.perft_layers(DEPTH);
 
         assert_eq!(totals, expected[..DEPTH]);
     }
+
+    /// Too slow for a debug build, run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_deep_perft_positions() {
+        for (fen, expected) in DEEP_PERFT {
+            let mut board = Board::from_fen(fen).unwrap();
+            assert_eq!(board.perft_layers(expected.len()), expected, "{fen}");
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_deep_parallel_perft_positions() {
+        // at least two threads, so the work is split even on one core
+        let threads = std::thread::available_parallelism().map_or(4, |n| n.get().max(2));
+        for (fen, expected) in DEEP_PERFT {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(
+                board.perft_layers_parallel(expected.len(), threads),
+                expected,
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_deep_perft_stats() {
+        check_stats(PERFT_FENS[0], &START_STATS);
+        check_stats(PERFT_FENS[1], &KIWIPETE_STATS);
+        check_stats(PERFT_FENS[2], &POS3_STATS);
+    }
 }